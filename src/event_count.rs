@@ -0,0 +1,132 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex};
+
+/// An eventcount: lets threads block until a condition on
+/// lock-free or optimistically-read pinned state becomes true, without
+/// taking a lock on the fast path.
+///
+/// Waiters follow a fixed protocol:
+///
+/// 1. Call [prepare_wait](Self::prepare_wait).
+/// 2. Check the condition. If it holds, call
+///    [cancel_wait](Self::cancel_wait) and proceed.
+/// 3. Otherwise, call [commit_wait](Self::commit_wait), which blocks
+///    unless a notification arrived after step 1. Then start over.
+///
+/// Producers update the state and then call
+/// [notify_one](Self::notify_one) or [notify_all](Self::notify_all),
+/// which are nearly free when nobody is waiting.
+#[derive(Debug, Default)]
+pub struct PinnedEventCount {
+    epoch: AtomicUsize,
+    waiters: AtomicUsize,
+    lock: Mutex<()>,
+    condvar: Condvar,
+}
+
+/// Returned by [PinnedEventCount::prepare_wait]. Must be passed to
+/// exactly one of `commit_wait` or `cancel_wait`.
+#[must_use]
+#[derive(Debug)]
+pub struct WaitKey {
+    epoch: usize,
+}
+
+impl PinnedEventCount {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Announces intent to wait. Notifications after this call wake
+    /// the subsequent `commit_wait`.
+    pub fn prepare_wait(&self) -> WaitKey {
+        self.waiters.fetch_add(1, Ordering::SeqCst);
+        WaitKey {
+            epoch: self.epoch.load(Ordering::SeqCst),
+        }
+    }
+
+    /// Abandons a wait because the condition already holds.
+    pub fn cancel_wait(&self, _key: WaitKey) {
+        self.waiters.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    /// Blocks until a notification has occurred since `key` was
+    /// obtained. Returns immediately if one already has.
+    pub fn commit_wait(&self, key: WaitKey) {
+        let mut lock = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        while self.epoch.load(Ordering::SeqCst) == key.epoch {
+            lock = self.condvar.wait(lock).unwrap_or_else(|e| e.into_inner());
+        }
+        drop(lock);
+        self.waiters.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    /// Wakes at least one committed waiter, if any.
+    pub fn notify_one(&self) {
+        if self.advance() {
+            self.condvar.notify_one();
+        }
+    }
+
+    /// Wakes all committed waiters.
+    pub fn notify_all(&self) {
+        if self.advance() {
+            self.condvar.notify_all();
+        }
+    }
+
+    /// Bumps the epoch and reports whether anyone may be waiting.
+    fn advance(&self) -> bool {
+        self.epoch.fetch_add(1, Ordering::SeqCst);
+        if self.waiters.load(Ordering::SeqCst) == 0 {
+            return false;
+        }
+        // A waiter that saw the old epoch holds the lock until it is
+        // inside Condvar::wait, so taking the lock here orders the
+        // notification after it.
+        drop(self.lock.lock().unwrap_or_else(|e| e.into_inner()));
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicBool;
+
+    #[test]
+    fn cancel_when_ready() {
+        let ec = PinnedEventCount::new();
+        let key = ec.prepare_wait();
+        ec.cancel_wait(key);
+        ec.notify_all();
+    }
+
+    #[test]
+    fn notified_before_commit() {
+        let ec = PinnedEventCount::new();
+        let key = ec.prepare_wait();
+        ec.notify_one();
+        // Does not block.
+        ec.commit_wait(key);
+    }
+
+    #[test]
+    fn wait_for_flag() {
+        let ec = PinnedEventCount::new();
+        let ready = AtomicBool::new(false);
+        std::thread::scope(|s| {
+            s.spawn(|| loop {
+                let key = ec.prepare_wait();
+                if ready.load(Ordering::Acquire) {
+                    ec.cancel_wait(key);
+                    break;
+                }
+                ec.commit_wait(key);
+            });
+            ready.store(true, Ordering::Release);
+            ec.notify_all();
+        });
+    }
+}
@@ -0,0 +1,267 @@
+use parking_lot::lock_api::RawMutex as _;
+use parking_lot::RawMutex;
+use std::cell::UnsafeCell;
+use std::ffi::c_void;
+use std::fmt;
+use std::marker::PhantomPinned;
+use std::ops::{Deref, DerefMut};
+use std::pin::Pin;
+
+/// The C-visible part of a [PinnedMutex]. C code only ever sees a
+/// pointer to it, from [PinnedMutex::handle], and passes that pointer
+/// to the `pinned_mutex_*` functions.
+#[repr(C)]
+pub struct PinnedMutexHandle {
+    raw: RawMutex,
+    /// Distance from the handle to the data. Fields never move
+    /// relative to each other, so this is fixed at construction.
+    data_offset: usize,
+}
+
+impl fmt::Debug for PinnedMutexHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PinnedMutexHandle").finish_non_exhaustive()
+    }
+}
+
+/// Provides [structural
+/// pinning](https://doc.rust-lang.org/std/pin/index.html#projections-and-structural-pinning)
+/// atop a lock that C code can take too.
+///
+/// Rust code locks it through [lock](Self::lock) as usual. C code
+/// receives the pointer returned by [handle](Self::handle) and calls
+/// [pinned_mutex_lock] and [pinned_mutex_unlock], which take the same
+/// underlying lock. Both sides are excluded from each other, and
+/// since a handle can only be obtained from a pinned mutex, the data
+/// stays put for as long as C can reach it.
+///
+/// `T` should be `#[repr(C)]` if C code reads or writes its fields.
+///
+/// The `pinned_mutex_*` functions are exported under fixed, unmangled
+/// names, so at most one version of this crate may enable `ffi` in a
+/// binary. If two semver-incompatible versions end up in the same
+/// dependency graph, both export the same symbols and linking fails.
+#[repr(C)]
+pub struct PinnedMutex<T> {
+    handle: PinnedMutexHandle,
+    data: UnsafeCell<T>,
+    /// C may hold a handle until the mutex is dropped, so the mutex
+    /// must stay put even when T is Unpin.
+    _pinned: PhantomPinned,
+}
+
+// SAFETY: RawMutex provides mutual exclusion, so sharing the mutex
+// only requires T to be sendable between threads, as with
+// std::sync::Mutex.
+unsafe impl<T: Send> Send for PinnedMutex<T> {}
+unsafe impl<T: Send> Sync for PinnedMutex<T> {}
+
+impl<T> PinnedMutex<T> {
+    pub fn new(init: T) -> Self {
+        let mut mutex = Self {
+            handle: PinnedMutexHandle {
+                raw: RawMutex::INIT,
+                data_offset: 0,
+            },
+            data: UnsafeCell::new(init),
+            _pinned: PhantomPinned,
+        };
+        mutex.handle.data_offset =
+            mutex.data.get() as usize - &mutex.handle as *const PinnedMutexHandle as usize;
+        mutex
+    }
+
+    /// Acquires the lock and returns a guard.
+    pub fn lock(self: Pin<&Self>) -> PinnedMutexGuard<'_, T> {
+        let mutex = self.get_ref();
+        mutex.handle.raw.lock();
+        PinnedMutexGuard { mutex }
+    }
+
+    /// Always succeeds, because the lock has no poisoning and cannot
+    /// report an error. Exists for code written against every
+    /// backend's `lock_checked`.
+    #[cfg_attr(docsrs, doc(cfg(feature = "fallible")))]
+    #[cfg(feature = "fallible")]
+    pub fn lock_checked(self: Pin<&Self>) -> Result<PinnedMutexGuard<'_, T>, crate::LockError> {
+        Ok(self.lock())
+    }
+
+    /// Attempts to acquire the lock without blocking.
+    pub fn try_lock(self: Pin<&Self>) -> Option<PinnedMutexGuard<'_, T>> {
+        self.get_ref().try_lock_inner()
+    }
+
+    /// Returns the pointer to pass to C. It is valid for as long as
+    /// the mutex is borrowed, and since the mutex is pinned, C may
+    /// keep it until the mutex is dropped. Keeping it any longer is
+    /// undefined behavior.
+    pub fn handle(self: Pin<&Self>) -> *const PinnedMutexHandle {
+        // The handle is the first field, and the pointer must cover
+        // the data too.
+        self.get_ref() as *const Self as *const PinnedMutexHandle
+    }
+
+    fn try_lock_inner(&self) -> Option<PinnedMutexGuard<'_, T>> {
+        if self.handle.raw.try_lock() {
+            Some(PinnedMutexGuard { mutex: self })
+        } else {
+            None
+        }
+    }
+}
+
+impl<T: Default> Default for PinnedMutex<T> {
+    fn default() -> Self {
+        Self::new(Default::default())
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for PinnedMutex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("PinnedMutex");
+        match self.try_lock_inner() {
+            Some(guard) => d.field("data", &&*guard),
+            None => d.field("data", &format_args!("<locked>")),
+        };
+        d.finish_non_exhaustive()
+    }
+}
+
+/// Provides access to mutex's contents. [Deref] to `&T` is always
+/// possible. [DerefMut] to `&mut T` is only possible if T is `Unpin`.
+///
+/// `as_ref` and `as_mut` project structural pinning.
+pub struct PinnedMutexGuard<'a, T: 'a> {
+    mutex: &'a PinnedMutex<T>,
+}
+
+impl<'a, T> PinnedMutexGuard<'a, T> {
+    /// Provides pinned access to the underlying T.
+    pub fn as_ref(&self) -> Pin<&T> {
+        // PinnedMutex::lock requires the mutex is pinned.
+        unsafe { Pin::new_unchecked(&*self.mutex.data.get()) }
+    }
+
+    /// Provides pinned mutable access to the underlying T.
+    pub fn as_mut(&mut self) -> Pin<&mut T> {
+        // PinnedMutex::lock requires the mutex is pinned.
+        // &mut self guarantees as_ref() cannot alias.
+        unsafe { Pin::new_unchecked(&mut *self.mutex.data.get()) }
+    }
+}
+
+impl<'a, T> Deref for PinnedMutexGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        // SAFETY: the guard holds the lock.
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<'a, T: Unpin> DerefMut for PinnedMutexGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        // SAFETY: the guard holds the lock, and T is Unpin.
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<'a, T> Drop for PinnedMutexGuard<'a, T> {
+    fn drop(&mut self) {
+        // SAFETY: the guard holds the lock.
+        unsafe { self.mutex.handle.raw.unlock() }
+    }
+}
+
+/// Returns the mutex's data, which C may access while it holds the
+/// lock. The data never moves.
+///
+/// # Safety
+///
+/// `handle` must come from [PinnedMutex::handle] on a mutex that is
+/// still alive.
+#[no_mangle]
+pub unsafe extern "C" fn pinned_mutex_data(handle: *const PinnedMutexHandle) -> *mut c_void {
+    let offset = (*handle).data_offset;
+    (handle as *mut u8).add(offset).cast()
+}
+
+/// Blocks until the lock is acquired, then returns the mutex's data.
+///
+/// # Safety
+///
+/// `handle` must be as in [pinned_mutex_data]. The lock is not
+/// reentrant, so the calling thread must not already hold it. Release
+/// it with [pinned_mutex_unlock].
+#[no_mangle]
+pub unsafe extern "C" fn pinned_mutex_lock(handle: *const PinnedMutexHandle) -> *mut c_void {
+    (*handle).raw.lock();
+    pinned_mutex_data(handle)
+}
+
+/// Acquires the lock and returns the mutex's data if the lock is
+/// free, or returns null without blocking.
+///
+/// # Safety
+///
+/// As for [pinned_mutex_lock].
+#[no_mangle]
+pub unsafe extern "C" fn pinned_mutex_try_lock(handle: *const PinnedMutexHandle) -> *mut c_void {
+    if (*handle).raw.try_lock() {
+        pinned_mutex_data(handle)
+    } else {
+        std::ptr::null_mut()
+    }
+}
+
+/// Releases a lock acquired by [pinned_mutex_lock] or
+/// [pinned_mutex_try_lock]. The data pointer must not be used after.
+///
+/// # Safety
+///
+/// `handle` must be as in [pinned_mutex_data], and the calling
+/// thread must hold the lock through C. A lock held by a Rust guard
+/// is released by dropping the guard.
+#[no_mangle]
+pub unsafe extern "C" fn pinned_mutex_unlock(handle: *const PinnedMutexHandle) {
+    (*handle).raw.unlock();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pin_project::pin_project;
+    use std::{marker::PhantomPinned, pin::pin};
+
+    #[pin_project(UnsafeUnpin)]
+    #[repr(C)]
+    struct Counter {
+        value: u64,
+        pinned: PhantomPinned,
+    }
+
+    #[test]
+    fn shared_with_c() {
+        let pm = pin!(PinnedMutex::new(Counter {
+            value: 0,
+            pinned: PhantomPinned,
+        }));
+        let pm = pm.as_ref();
+        let handle = pm.handle();
+        unsafe {
+            let data = pinned_mutex_lock(handle).cast::<Counter>();
+            (*data).value += 1;
+            assert!(pm.try_lock().is_none());
+            pinned_mutex_unlock(handle);
+        }
+        let mut guard = pm.lock();
+        *guard.as_mut().project().value += 1;
+        assert!(unsafe { pinned_mutex_try_lock(handle) }.is_null());
+        drop(guard);
+        unsafe {
+            let data = pinned_mutex_try_lock(handle).cast::<Counter>();
+            assert_eq!(2, (*data).value);
+            pinned_mutex_unlock(handle);
+        }
+    }
+}
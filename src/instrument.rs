@@ -0,0 +1,317 @@
+//! Optional lock instrumentation shared by the backends. Without the
+//! instrumentation features, the state stored in mutexes and guards
+//! is zero-sized and every method is a no-op.
+
+use std::marker::PhantomData;
+#[cfg(feature = "metrics")]
+use std::time::Instant;
+
+/// A mutex's instrumentation state. Unnamed mutexes are not
+/// instrumented.
+#[derive(Debug, Default)]
+pub(crate) struct Instrument {
+    #[cfg(feature = "metrics")]
+    name: Option<&'static str>,
+    #[cfg(feature = "tracy")]
+    tracy: Option<tracy::Lockable>,
+}
+
+impl Instrument {
+    pub(crate) const fn new() -> Self {
+        Self {
+            #[cfg(feature = "metrics")]
+            name: None,
+            #[cfg(feature = "tracy")]
+            tracy: None,
+        }
+    }
+
+    #[cfg_attr(
+        not(any(feature = "metrics", feature = "tracy")),
+        allow(unused_variables)
+    )]
+    pub(crate) fn named(name: &'static str) -> Self {
+        Self {
+            #[cfg(feature = "metrics")]
+            name: Some(name),
+            #[cfg(feature = "tracy")]
+            tracy: Some(tracy::Lockable::announce(name)),
+        }
+    }
+
+    /// Call immediately before blocking on the lock.
+    pub(crate) fn start_acquire(&self) -> AcquireTimer<'_> {
+        AcquireTimer {
+            instrument: self,
+            #[cfg(feature = "metrics")]
+            started: self.name.map(|_| Instant::now()),
+            #[cfg(feature = "tracy")]
+            tracy_waiting: self.tracy.as_ref().is_some_and(|t| t.before_lock()),
+        }
+    }
+}
+
+pub(crate) struct AcquireTimer<'a> {
+    instrument: &'a Instrument,
+    #[cfg(feature = "metrics")]
+    started: Option<Instant>,
+    #[cfg(feature = "tracy")]
+    tracy_waiting: bool,
+}
+
+impl<'a> AcquireTimer<'a> {
+    /// Call once the lock is held. Records the time spent waiting and
+    /// starts timing the hold.
+    pub(crate) fn acquired(self) -> HoldTimer<'a> {
+        #[cfg(feature = "metrics")]
+        if let (Some(name), Some(started)) = (self.instrument.name, self.started) {
+            metrics::histogram!("pinned_mutex.acquire.wait_ns", "lock" => name)
+                .record(started.elapsed().as_nanos() as f64);
+        }
+        #[cfg(feature = "tracy")]
+        if let (Some(tracy), true) = (&self.instrument.tracy, self.tracy_waiting) {
+            tracy.after_lock();
+        }
+        HoldTimer::new(self.instrument)
+    }
+}
+
+/// Records the end of a hold when dropped.
+#[derive(Debug)]
+pub(crate) struct HoldTimer<'a> {
+    #[cfg(any(feature = "metrics", feature = "tracy"))]
+    instrument: &'a Instrument,
+    #[cfg(feature = "metrics")]
+    started: Option<Instant>,
+    /// False while paused, when there is no hold to record.
+    #[cfg(any(feature = "metrics", feature = "tracy"))]
+    held: bool,
+    _marker: PhantomData<&'a Instrument>,
+}
+
+impl<'a> HoldTimer<'a> {
+    #[cfg_attr(
+        not(any(feature = "metrics", feature = "tracy")),
+        allow(unused_variables)
+    )]
+    fn new(instrument: &'a Instrument) -> Self {
+        Self {
+            #[cfg(any(feature = "metrics", feature = "tracy"))]
+            instrument,
+            #[cfg(feature = "metrics")]
+            started: instrument.name.map(|_| Instant::now()),
+            #[cfg(any(feature = "metrics", feature = "tracy"))]
+            held: true,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Starts timing a hold that began without a measured wait, such
+    /// as a reacquisition after a condvar wait.
+    pub(crate) fn start(instrument: &'a Instrument) -> Self {
+        #[cfg(feature = "tracy")]
+        if let Some(tracy) = &instrument.tracy {
+            tracy.after_try_lock();
+        }
+        Self::new(instrument)
+    }
+
+    /// Records the hold now, as when the lock is released by a
+    /// condvar wait.
+    pub(crate) fn finish(self) {}
+
+    /// Never records the hold, as when a guard is leaked and the lock
+    /// is never released.
+    #[cfg(feature = "parking_lot")]
+    #[cfg_attr(
+        not(any(feature = "metrics", feature = "tracy")),
+        allow(clippy::forget_non_drop)
+    )]
+    pub(crate) fn leak(self) {
+        std::mem::forget(self)
+    }
+
+    /// Records the hold now, as when the lock is temporarily released
+    /// while its guard lives on.
+    #[cfg(feature = "parking_lot")]
+    pub(crate) fn pause(&mut self) {
+        #[cfg(any(feature = "metrics", feature = "tracy"))]
+        self.record();
+    }
+
+    /// Starts timing a new hold after [pause](Self::pause), once the
+    /// lock is reacquired.
+    #[cfg(feature = "parking_lot")]
+    pub(crate) fn resume(&mut self) {
+        #[cfg(any(feature = "metrics", feature = "tracy"))]
+        if !self.held {
+            self.held = true;
+            #[cfg(feature = "metrics")]
+            {
+                self.started = self.instrument.name.map(|_| Instant::now());
+            }
+            #[cfg(feature = "tracy")]
+            if let Some(tracy) = &self.instrument.tracy {
+                tracy.after_try_lock();
+            }
+        }
+    }
+
+    #[cfg(any(feature = "metrics", feature = "tracy"))]
+    fn record(&mut self) {
+        if !self.held {
+            return;
+        }
+        self.held = false;
+        #[cfg(feature = "metrics")]
+        if let (Some(name), Some(started)) = (self.instrument.name, self.started) {
+            metrics::histogram!("pinned_mutex.hold_ns", "lock" => name)
+                .record(started.elapsed().as_nanos() as f64);
+        }
+        #[cfg(feature = "tracy")]
+        if let Some(tracy) = &self.instrument.tracy {
+            tracy.after_unlock();
+        }
+    }
+}
+
+#[cfg(any(feature = "metrics", feature = "tracy"))]
+impl<'a> Drop for HoldTimer<'a> {
+    fn drop(&mut self) {
+        self.record();
+    }
+}
+
+#[cfg(feature = "tracy")]
+mod tracy {
+    use std::os::raw::c_char;
+    use std::ptr;
+    use tracy_client_sys::*;
+
+    struct SourceLocation(___tracy_source_location_data);
+
+    // SAFETY: the location only points at static strings.
+    unsafe impl Sync for SourceLocation {}
+
+    static LOCATION: SourceLocation = SourceLocation(___tracy_source_location_data {
+        name: ptr::null(),
+        function: c"PinnedMutex".as_ptr(),
+        file: concat!(file!(), "\0").as_ptr() as *const c_char,
+        line: line!(),
+        color: 0,
+    });
+
+    /// A Tracy lockable context, shown in Tracy's lock view.
+    #[derive(Debug)]
+    pub(super) struct Lockable(*mut __tracy_lockable_context_data);
+
+    // SAFETY: lockable contexts are used from whichever thread takes
+    // the lock, and Tracy synchronizes them internally.
+    unsafe impl Send for Lockable {}
+    unsafe impl Sync for Lockable {}
+
+    impl Lockable {
+        pub(super) fn announce(name: &str) -> Self {
+            // SAFETY: LOCATION is static, and Tracy copies the name.
+            unsafe {
+                let ctx = ___tracy_announce_lockable_ctx(&LOCATION.0);
+                ___tracy_custom_name_lockable_ctx(ctx, name.as_ptr() as *const c_char, name.len());
+                Self(ctx)
+            }
+        }
+
+        /// Returns whether `after_lock` must be called.
+        pub(super) fn before_lock(&self) -> bool {
+            // SAFETY: the context lives until self is dropped.
+            unsafe { ___tracy_before_lock_lockable_ctx(self.0) != 0 }
+        }
+
+        pub(super) fn after_lock(&self) {
+            // SAFETY: as above.
+            unsafe { ___tracy_after_lock_lockable_ctx(self.0) }
+        }
+
+        pub(super) fn after_try_lock(&self) {
+            // SAFETY: as above.
+            unsafe { ___tracy_after_try_lock_lockable_ctx(self.0, 1) }
+        }
+
+        pub(super) fn after_unlock(&self) {
+            // SAFETY: as above.
+            unsafe { ___tracy_after_unlock_lockable_ctx(self.0) }
+        }
+    }
+
+    impl Drop for Lockable {
+        fn drop(&mut self) {
+            // SAFETY: guards borrow the mutex that owns self, so none
+            // remain.
+            unsafe { ___tracy_terminate_lockable_ctx(self.0) }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "metrics"))]
+mod tests {
+    use crate::std::PinnedMutex;
+    use metrics::{
+        Counter, Gauge, Histogram, HistogramFn, Key, KeyName, Metadata, Recorder, SharedString,
+        Unit,
+    };
+    use std::pin::pin;
+    use std::sync::{Arc, Mutex};
+
+    /// Records the name and lock label of every histogram sample.
+    #[derive(Default)]
+    struct Samples(Arc<Mutex<Vec<(String, String)>>>);
+
+    struct Sample(Key, Arc<Mutex<Vec<(String, String)>>>);
+
+    impl HistogramFn for Sample {
+        fn record(&self, _value: f64) {
+            let label = self.0.labels().next().unwrap().value().to_owned();
+            let name = self.0.name().to_owned();
+            self.1.lock().unwrap().push((name, label));
+        }
+    }
+
+    impl Recorder for Samples {
+        fn describe_counter(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn describe_gauge(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn describe_histogram(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+        fn register_counter(&self, _: &Key, _: &Metadata<'_>) -> Counter {
+            Counter::noop()
+        }
+
+        fn register_gauge(&self, _: &Key, _: &Metadata<'_>) -> Gauge {
+            Gauge::noop()
+        }
+
+        fn register_histogram(&self, key: &Key, _: &Metadata<'_>) -> Histogram {
+            Histogram::from_arc(Arc::new(Sample(key.clone(), self.0.clone())))
+        }
+    }
+
+    #[test]
+    fn named_lock_records_wait_and_hold() {
+        let recorder = Samples::default();
+        metrics::with_local_recorder(&recorder, || {
+            let named = pin!(PinnedMutex::new(0).named("cache"));
+            drop(named.as_ref().lock());
+            let unnamed = pin!(PinnedMutex::new(0));
+            drop(unnamed.as_ref().lock());
+        });
+        let samples = recorder.0.lock().unwrap();
+        assert_eq!(
+            *samples,
+            [
+                (
+                    "pinned_mutex.acquire.wait_ns".to_owned(),
+                    "cache".to_owned()
+                ),
+                ("pinned_mutex.hold_ns".to_owned(), "cache".to_owned()),
+            ]
+        );
+    }
+}
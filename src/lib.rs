@@ -1,9 +1,160 @@
 #![cfg_attr(docsrs, feature(doc_cfg))]
+#![cfg_attr(feature = "nightly", feature(reentrant_lock))]
 
-/// Structurally-pinned wrappers for `std::sync`'s Mutex types.
+/// Structurally-pinned wrappers for `std::sync`'s Mutex, RwLock, and
+/// (with the `nightly` feature) ReentrantLock types.
 pub mod std;
 
-/// Structurally-pinned wrappers for `parking_lot`'s Mutex types.
+/// Structurally-pinned wrappers for `parking_lot`'s Mutex and RwLock
+/// types.
 #[cfg_attr(docsrs, doc(cfg(feature = "parking_lot")))]
 #[cfg(feature = "parking_lot")]
 pub mod parking_lot;
+
+/// A structurally-pinned mutex that C code can lock too.
+#[cfg_attr(docsrs, doc(cfg(feature = "ffi")))]
+#[cfg(feature = "ffi")]
+pub mod ffi;
+
+/// An eventcount for blocking on optimistically-checked pinned state.
+pub mod event_count;
+
+/// A structurally-pinned mutex for single-threaded use.
+pub mod local;
+
+/// A concurrent map of pinned values with per-bucket locks.
+pub mod locked_map;
+
+/// A blocking single-producer single-consumer ring of pinned slots.
+pub mod ring;
+
+/// Lock-free pinned cells whose access is granted by a branded token.
+pub mod token;
+
+/// A triple buffer for handing pinned snapshots from one writer to one
+/// reader without blocking.
+pub mod triple_buffer;
+
+/// Structurally-pinned wrappers for Apple's os_unfair_lock.
+#[cfg_attr(docsrs, doc(cfg(all(target_vendor = "apple", feature = "macos"))))]
+#[cfg(all(target_vendor = "apple", feature = "macos"))]
+pub mod macos;
+
+/// Structurally-pinned wrappers for POSIX `pthread_rwlock_t`.
+#[cfg_attr(docsrs, doc(cfg(all(unix, feature = "pthread"))))]
+#[cfg(all(unix, feature = "pthread"))]
+pub mod pthread;
+
+/// Structurally-pinned wrappers for native Windows SRWLOCK and
+/// CONDITION_VARIABLE.
+#[cfg_attr(docsrs, doc(cfg(all(windows, feature = "windows"))))]
+#[cfg(all(windows, feature = "windows"))]
+pub mod windows;
+
+mod instrument;
+
+use ::std::pin::Pin;
+use ::std::sync::Arc;
+use ::std::time::{Duration, Instant};
+
+/// Borrows a pinned Arc's contents for `'static`, for owned guards
+/// that store the Arc next to a guard borrowed from it.
+///
+/// # Safety
+///
+/// The returned reference must not outlive `arc`'s reference count.
+/// Owned guards declare the borrowed guard before the Arc, so it is
+/// dropped first.
+pub(crate) unsafe fn pin_arc_static<T: ?Sized>(arc: &Pin<Arc<T>>) -> Pin<&'static T> {
+    // SAFETY: the Arc's contents never move, and the caller keeps
+    // them alive for as long as the reference is used.
+    unsafe { Pin::new_unchecked(&*(&**arc as *const T)) }
+}
+
+/// When a timed wait gives up. Timed methods accept anything that
+/// converts into a `Deadline`, so a [Duration] or an [Instant] can be
+/// passed directly.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Deadline {
+    /// Expires this long after the timed method is called.
+    Timeout(Duration),
+    /// Expires at this instant.
+    At(Instant),
+    /// Never expires.
+    Never,
+}
+
+impl Deadline {
+    /// Returns the instant this deadline expires, measuring a
+    /// [Timeout](Self::Timeout) from now. Returns `None` if it never
+    /// expires, including when the instant is too far away to
+    /// represent.
+    pub fn to_instant(self) -> Option<Instant> {
+        match self {
+            Deadline::Timeout(timeout) => Instant::now().checked_add(timeout),
+            Deadline::At(at) => Some(at),
+            Deadline::Never => None,
+        }
+    }
+}
+
+impl From<Duration> for Deadline {
+    fn from(timeout: Duration) -> Self {
+        Deadline::Timeout(timeout)
+    }
+}
+
+impl From<Instant> for Deadline {
+    fn from(at: Instant) -> Self {
+        Deadline::At(at)
+    }
+}
+
+impl From<Option<Instant>> for Deadline {
+    fn from(at: Option<Instant>) -> Self {
+        at.map_or(Deadline::Never, Deadline::At)
+    }
+}
+
+/// Which waiters a [std::NotifyingGuard] wakes when it is dropped
+/// after a mutation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum NotifyPolicy {
+    /// Wake one waiter.
+    One,
+    /// Wake every waiter.
+    All,
+}
+
+/// Why a `lock_checked` call failed. Every backend returns this same
+/// type, so code generic over backends can handle failures uniformly.
+#[cfg_attr(docsrs, doc(cfg(feature = "fallible")))]
+#[cfg(feature = "fallible")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum LockError {
+    /// A thread panicked while holding the lock.
+    Poisoned,
+    /// Acquiring the lock would never return, because the current
+    /// thread already holds it, or could deadlock, because it breaks
+    /// the order of a [std::HierarchicalPinnedMutex].
+    WouldDeadlock,
+    /// The platform lock failed with this OS error code.
+    Os(i32),
+}
+
+#[cfg(feature = "fallible")]
+impl ::std::fmt::Display for LockError {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+        match self {
+            LockError::Poisoned => f.write_str("lock poisoned by a panicking holder"),
+            LockError::WouldDeadlock => f.write_str("acquiring the lock would deadlock"),
+            LockError::Os(code) => {
+                write!(f, "{}", ::std::io::Error::from_raw_os_error(*code))
+            }
+        }
+    }
+}
+
+#[cfg(feature = "fallible")]
+impl ::std::error::Error for LockError {}
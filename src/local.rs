@@ -0,0 +1,279 @@
+use std::cell::{Cell, UnsafeCell};
+use std::fmt;
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+use std::pin::Pin;
+use std::rc::Rc;
+
+#[cfg(feature = "fallible")]
+use crate::LockError;
+
+/// Provides [structural
+/// pinning](https://doc.rust-lang.org/std/pin/index.html#projections-and-structural-pinning)
+/// for state that never leaves its thread.
+///
+/// LocalPinnedMutex is neither Send nor Sync, so it needs no atomics.
+/// A plain flag tracks whether a guard exists. Since no other thread
+/// can release the lock, locking it twice could never succeed, and
+/// `lock` panics instead of hanging.
+pub struct LocalPinnedMutex<T> {
+    locked: Cell<bool>,
+    data: UnsafeCell<T>,
+    _not_send: PhantomData<*mut ()>,
+}
+
+impl<T> LocalPinnedMutex<T> {
+    pub const fn new(init: T) -> Self {
+        Self {
+            locked: Cell::new(false),
+            data: UnsafeCell::new(init),
+            _not_send: PhantomData,
+        }
+    }
+
+    /// Acquires the lock and returns a guard.
+    ///
+    /// Panics if the lock is already held.
+    #[track_caller]
+    pub fn lock(self: Pin<&Self>) -> LocalPinnedMutexGuard<'_, T> {
+        match self.try_lock() {
+            Some(guard) => guard,
+            None => panic!("LocalPinnedMutex is already locked"),
+        }
+    }
+
+    /// Like [lock](Self::lock), but returns
+    /// [LockError::WouldDeadlock] instead of panicking if the lock is
+    /// already held.
+    #[cfg_attr(docsrs, doc(cfg(feature = "fallible")))]
+    #[cfg(feature = "fallible")]
+    pub fn lock_checked(self: Pin<&Self>) -> Result<LocalPinnedMutexGuard<'_, T>, LockError> {
+        self.try_lock().ok_or(LockError::WouldDeadlock)
+    }
+
+    /// Acquires the lock if it is not already held.
+    pub fn try_lock(self: Pin<&Self>) -> Option<LocalPinnedMutexGuard<'_, T>> {
+        self.get_ref().try_lock_inner()
+    }
+
+    /// Returns true if a guard currently holds the lock.
+    pub fn is_locked(&self) -> bool {
+        self.locked.get()
+    }
+
+    /// Like [lock](Self::lock), but the guard keeps the mutex alive
+    /// instead of borrowing it, so a single-threaded task can hold it
+    /// without threading a lifetime through.
+    ///
+    /// Panics if the lock is already held.
+    #[track_caller]
+    pub fn borrow_pin_owned(self: Pin<Rc<Self>>) -> LocalPinnedMutexOwnedGuard<T> {
+        match self.try_borrow_pin_owned() {
+            Some(guard) => guard,
+            None => panic!("LocalPinnedMutex is already locked"),
+        }
+    }
+
+    /// Like [try_lock](Self::try_lock), but returns an owned guard as
+    /// in [borrow_pin_owned](Self::borrow_pin_owned).
+    pub fn try_borrow_pin_owned(self: Pin<Rc<Self>>) -> Option<LocalPinnedMutexOwnedGuard<T>> {
+        if self.locked.replace(true) {
+            None
+        } else {
+            Some(LocalPinnedMutexOwnedGuard { mutex: self })
+        }
+    }
+
+    fn try_lock_inner(&self) -> Option<LocalPinnedMutexGuard<'_, T>> {
+        if self.locked.replace(true) {
+            None
+        } else {
+            Some(LocalPinnedMutexGuard { mutex: self })
+        }
+    }
+}
+
+impl<T: Default> Default for LocalPinnedMutex<T> {
+    fn default() -> Self {
+        Self::new(Default::default())
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for LocalPinnedMutex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("LocalPinnedMutex");
+        match self.try_lock_inner() {
+            Some(guard) => d.field("data", &&*guard),
+            None => d.field("data", &format_args!("<locked>")),
+        };
+        d.finish()
+    }
+}
+
+/// Provides access to a [LocalPinnedMutex]'s contents. [Deref] to
+/// `&T` is always possible. [DerefMut] to `&mut T` is only possible
+/// if T is `Unpin`.
+///
+/// `as_ref` and `as_mut` project structural pinning.
+pub struct LocalPinnedMutexGuard<'a, T: 'a> {
+    mutex: &'a LocalPinnedMutex<T>,
+}
+
+impl<'a, T> LocalPinnedMutexGuard<'a, T> {
+    /// Provides pinned access to the underlying T.
+    pub fn as_ref(&self) -> Pin<&T> {
+        // LocalPinnedMutex::lock requires the mutex is pinned.
+        unsafe { Pin::new_unchecked(&*self.mutex.data.get()) }
+    }
+
+    /// Provides pinned mutable access to the underlying T.
+    pub fn as_mut(&mut self) -> Pin<&mut T> {
+        // LocalPinnedMutex::lock requires the mutex is pinned.
+        // &mut self guarantees as_ref() cannot alias.
+        unsafe { Pin::new_unchecked(&mut *self.mutex.data.get()) }
+    }
+}
+
+impl<'a, T> Deref for LocalPinnedMutexGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        // SAFETY: the guard holds the lock.
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<'a, T: Unpin> DerefMut for LocalPinnedMutexGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        // SAFETY: the guard holds the lock, and T is Unpin.
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<'a, T> Drop for LocalPinnedMutexGuard<'a, T> {
+    fn drop(&mut self) {
+        self.mutex.locked.set(false);
+    }
+}
+
+/// A [LocalPinnedMutexGuard] that owns a reference count on its
+/// mutex, returned by [LocalPinnedMutex::borrow_pin_owned].
+pub struct LocalPinnedMutexOwnedGuard<T> {
+    mutex: Pin<Rc<LocalPinnedMutex<T>>>,
+}
+
+impl<T> LocalPinnedMutexOwnedGuard<T> {
+    /// Provides pinned access to the underlying T.
+    pub fn as_ref(&self) -> Pin<&T> {
+        // borrow_pin_owned requires the mutex is pinned.
+        unsafe { Pin::new_unchecked(&*self.mutex.data.get()) }
+    }
+
+    /// Provides pinned mutable access to the underlying T.
+    pub fn as_mut(&mut self) -> Pin<&mut T> {
+        // borrow_pin_owned requires the mutex is pinned.
+        // &mut self guarantees as_ref() cannot alias.
+        unsafe { Pin::new_unchecked(&mut *self.mutex.data.get()) }
+    }
+}
+
+impl<T> Deref for LocalPinnedMutexOwnedGuard<T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        // SAFETY: the guard holds the lock.
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<T: Unpin> DerefMut for LocalPinnedMutexOwnedGuard<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        // SAFETY: the guard holds the lock, and T is Unpin.
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<T> Drop for LocalPinnedMutexOwnedGuard<T> {
+    fn drop(&mut self) {
+        self.mutex.locked.set(false);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pin_project::pin_project;
+    use std::{marker::PhantomPinned, pin::pin};
+
+    #[pin_project(UnsafeUnpin)]
+    struct MustPin {
+        value: u32,
+        pinned: PhantomPinned,
+    }
+
+    impl MustPin {
+        fn new() -> Self {
+            Self {
+                value: 0,
+                pinned: PhantomPinned,
+            }
+        }
+
+        fn inc(self: Pin<&mut Self>) -> u32 {
+            let value = self.project().value;
+            let prev = *value;
+            *value += 1;
+            prev
+        }
+
+        fn get(self: Pin<&Self>) -> u32 {
+            *self.project_ref().value
+        }
+    }
+
+    #[test]
+    fn pinned_method() {
+        let pm = pin!(LocalPinnedMutex::new(MustPin::new()));
+        let mut locked = pm.as_ref().lock();
+        assert_eq!(0, locked.as_mut().inc());
+        assert_eq!(1, locked.as_ref().get());
+        assert!(pm.as_ref().try_lock().is_none());
+        drop(locked);
+        assert!(pm.as_ref().try_lock().is_some());
+    }
+
+    #[test]
+    #[should_panic(expected = "already locked")]
+    fn relock_panics() {
+        let pm = pin!(LocalPinnedMutex::new(0));
+        let _a = pm.as_ref().lock();
+        let _b = pm.as_ref().lock();
+    }
+
+    #[test]
+    fn owned_guard_keeps_mutex_alive() {
+        let pm = Rc::pin(LocalPinnedMutex::new(MustPin::new()));
+        let mut locked = pm.clone().borrow_pin_owned();
+        assert!(pm.clone().try_borrow_pin_owned().is_none());
+        drop(pm);
+        assert_eq!(0, locked.as_mut().inc());
+        assert_eq!(1, locked.as_ref().get());
+    }
+
+    #[test]
+    #[cfg(feature = "fallible")]
+    fn relock_checked_fails() {
+        let pm = pin!(LocalPinnedMutex::new(0));
+        let _a = pm.as_ref().lock_checked().unwrap();
+        assert_eq!(
+            Some(LockError::WouldDeadlock),
+            pm.as_ref().lock_checked().err()
+        );
+    }
+
+    #[test]
+    fn debug() {
+        let pm = pin!(LocalPinnedMutex::new(7));
+        assert_eq!("LocalPinnedMutex { data: 7 }", format!("{:?}", pm));
+        let _locked = pm.as_ref().lock();
+        assert_eq!("LocalPinnedMutex { data: <locked> }", format!("{:?}", pm));
+    }
+}
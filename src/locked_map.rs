@@ -0,0 +1,241 @@
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hash};
+use std::ops::{Deref, DerefMut};
+use std::pin::Pin;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+type Bucket<K, V> = Vec<(K, Pin<Box<V>>)>;
+
+/// A concurrent hash map whose values are pinned.
+///
+/// Each value lives in its own allocation, so its address is stable
+/// and `V` may be `!Unpin`. Keys are spread over a fixed number of
+/// buckets, each behind its own lock. A [PinnedLockedMapGuard] holds
+/// its bucket's lock, so operations on other buckets proceed
+/// concurrently, and [remove](Self::remove) waits until no guard
+/// refers to the bucket.
+///
+/// A thread must not hold two guards at once, or hold a guard while
+/// calling [remove](Self::remove). Keys that share a bucket share a
+/// lock, so the second call deadlocks or panics when they collide.
+/// Bucket assignment is randomized per map, so a collision may show
+/// up in one run and not the next. Drop each guard before asking for
+/// another.
+///
+/// Panics do not poison the map. The buckets stay consistent when an
+/// `init` or a guard holder panics, so later calls proceed, but a value
+/// may be left however the panicking holder left it.
+pub struct PinnedLockedMap<K, V> {
+    buckets: Box<[Mutex<Bucket<K, V>>]>,
+    hasher: RandomState,
+}
+
+impl<K: Hash + Eq, V> PinnedLockedMap<K, V> {
+    /// Creates a map with 64 buckets.
+    pub fn new() -> Self {
+        Self::with_buckets(64)
+    }
+
+    /// Creates a map with `count` buckets. The count never changes,
+    /// so choose it for the expected number of concurrent users.
+    ///
+    /// Panics if `count` is zero.
+    pub fn with_buckets(count: usize) -> Self {
+        assert!(count > 0, "PinnedLockedMap needs at least one bucket");
+        Self {
+            buckets: (0..count).map(|_| Mutex::default()).collect(),
+            hasher: RandomState::new(),
+        }
+    }
+
+    /// Locks the value for `key`'s bucket and returns a guard to it,
+    /// if present.
+    ///
+    /// Deadlocks or panics if this thread already holds a guard into
+    /// the same bucket. Never hold two guards at once; see
+    /// [PinnedLockedMap].
+    pub fn get_pin<Q>(&self, key: &Q) -> Option<PinnedLockedMapGuard<'_, K, V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let bucket = self.lock_bucket(key);
+        let index = bucket.iter().position(|(k, _)| k.borrow() == key)?;
+        Some(PinnedLockedMapGuard { bucket, index })
+    }
+
+    /// Returns a guard to the value for `key`, first inserting the
+    /// result of `init` if the key is absent. `init` runs with the
+    /// bucket locked, and the value is pinned in place from then on.
+    ///
+    /// Deadlocks or panics if this thread already holds a guard into
+    /// the same bucket. Never hold two guards at once; see
+    /// [PinnedLockedMap].
+    pub fn insert_with(&self, key: K, init: impl FnOnce() -> V) -> PinnedLockedMapGuard<'_, K, V> {
+        let mut bucket = self.lock_bucket(&key);
+        let index = match bucket.iter().position(|(k, _)| *k == key) {
+            Some(index) => index,
+            None => {
+                bucket.push((key, Box::pin(init())));
+                bucket.len() - 1
+            }
+        };
+        PinnedLockedMapGuard { bucket, index }
+    }
+
+    /// Removes `key`'s value and returns it, still pinned. Blocks while
+    /// any guard into the same bucket is outstanding.
+    pub fn remove<Q>(&self, key: &Q) -> Option<Pin<Box<V>>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let mut bucket = self.lock_bucket(key);
+        let index = bucket.iter().position(|(k, _)| k.borrow() == key)?;
+        Some(bucket.swap_remove(index).1)
+    }
+
+    fn lock_bucket<Q: Hash + ?Sized>(&self, key: &Q) -> MutexGuard<'_, Bucket<K, V>> {
+        let index = self.hasher.hash_one(key) as usize % self.buckets.len();
+        // Bucket updates cannot be interrupted halfway, so the poison
+        // flag says nothing about the bucket itself.
+        self.buckets[index]
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<K: Hash + Eq, V> Default for PinnedLockedMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> fmt::Debug for PinnedLockedMap<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PinnedLockedMap")
+            .field("buckets", &self.buckets.len())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Provides access to a value in a [PinnedLockedMap] while holding
+/// its bucket's lock. [Deref] to `&V` is always possible. [DerefMut]
+/// to `&mut V` is only possible if V is `Unpin`.
+///
+/// `as_ref` and `as_mut` project structural pinning.
+///
+/// The guard locks its whole bucket, not just its key. Drop it before
+/// taking another guard from the same map on this thread, because
+/// the other key may share the bucket. See [PinnedLockedMap].
+pub struct PinnedLockedMapGuard<'a, K, V> {
+    bucket: MutexGuard<'a, Bucket<K, V>>,
+    index: usize,
+}
+
+impl<'a, K, V> PinnedLockedMapGuard<'a, K, V> {
+    /// Returns the value's key.
+    pub fn key(&self) -> &K {
+        &self.bucket[self.index].0
+    }
+
+    /// Provides pinned access to the value.
+    pub fn as_ref(&self) -> Pin<&V> {
+        self.bucket[self.index].1.as_ref()
+    }
+
+    /// Provides pinned mutable access to the value.
+    pub fn as_mut(&mut self) -> Pin<&mut V> {
+        self.bucket[self.index].1.as_mut()
+    }
+}
+
+impl<'a, K, V> Deref for PinnedLockedMapGuard<'a, K, V> {
+    type Target = V;
+    fn deref(&self) -> &Self::Target {
+        &self.bucket[self.index].1
+    }
+}
+
+impl<'a, K, V: Unpin> DerefMut for PinnedLockedMapGuard<'a, K, V> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.bucket[self.index].1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pin_project::pin_project;
+    use std::marker::PhantomPinned;
+
+    #[pin_project(UnsafeUnpin)]
+    struct Session {
+        requests: u32,
+        pinned: PhantomPinned,
+    }
+
+    impl Session {
+        fn new() -> Self {
+            Self {
+                requests: 0,
+                pinned: PhantomPinned,
+            }
+        }
+    }
+
+    #[test]
+    fn insert_get_remove() {
+        let map = PinnedLockedMap::new();
+        *map.insert_with(7, Session::new).as_mut().project().requests += 1;
+        *map.insert_with(7, || unreachable!())
+            .as_mut()
+            .project()
+            .requests += 1;
+        let guard = map.get_pin(&7).unwrap();
+        assert_eq!(7, *guard.key());
+        assert_eq!(2, guard.requests);
+        drop(guard);
+        assert!(map.get_pin(&8).is_none());
+        assert_eq!(2, map.remove(&7).unwrap().requests);
+        assert!(map.remove(&7).is_none());
+    }
+
+    #[test]
+    fn remove_waits_for_guard() {
+        let map = PinnedLockedMap::with_buckets(1);
+        let mut guard = map.insert_with("a", || 1);
+        let (started, wait_started) = std::sync::mpsc::channel();
+        std::thread::scope(|s| {
+            let remover = s.spawn(|| {
+                started.send(()).unwrap();
+                *map.remove("a").unwrap()
+            });
+            wait_started.recv().unwrap();
+            // Whenever the remover reaches remove, it cannot finish
+            // until this write is released with the guard.
+            *guard = 2;
+            drop(guard);
+            assert_eq!(2, remover.join().unwrap());
+        });
+    }
+
+    #[test]
+    fn panics_do_not_poison() {
+        let map = PinnedLockedMap::with_buckets(1);
+        let panicked = std::panic::catch_unwind(|| {
+            map.insert_with(1, || panic!("init failed"));
+        });
+        assert!(panicked.is_err());
+        let panicked = std::panic::catch_unwind(|| {
+            let _guard = map.insert_with(2, || 20);
+            panic!("holder failed");
+        });
+        assert!(panicked.is_err());
+        assert!(map.get_pin(&1).is_none());
+        assert_eq!(20, *map.get_pin(&2).unwrap());
+        assert_eq!(30, *map.insert_with(3, || 30));
+    }
+}
@@ -0,0 +1,252 @@
+use std::cell::UnsafeCell;
+use std::fmt;
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[cfg(feature = "fallible")]
+use crate::LockError;
+
+/// Provides [structural
+/// pinning](https://doc.rust-lang.org/std/pin/index.html#projections-and-structural-pinning)
+/// atop
+/// [os_unfair_lock](https://developer.apple.com/documentation/os/os_unfair_lock).
+///
+/// os_unfair_lock must not move while it is held, which pinning
+/// guarantees.
+pub struct PinnedMutex<T> {
+    lock: UnsafeCell<libc::os_unfair_lock>,
+    waiters: AtomicUsize,
+    data: UnsafeCell<T>,
+}
+
+// SAFETY: os_unfair_lock provides mutual exclusion, so sharing the
+// mutex only requires T to be sendable between threads, as with
+// std::sync::Mutex.
+unsafe impl<T: Send> Send for PinnedMutex<T> {}
+unsafe impl<T: Send> Sync for PinnedMutex<T> {}
+
+impl<T> PinnedMutex<T> {
+    pub fn new(init: T) -> Self {
+        Self {
+            lock: UnsafeCell::new(libc::OS_UNFAIR_LOCK_INIT),
+            waiters: AtomicUsize::new(0),
+            data: UnsafeCell::new(init),
+        }
+    }
+
+    /// Acquires the lock and returns a guard.
+    ///
+    /// os_unfair_lock does not support poisoning. Neither does this.
+    pub fn lock(self: Pin<&Self>) -> PinnedMutexGuard<'_, T> {
+        let mutex = self.get_ref();
+        if let Some(guard) = mutex.try_lock_inner() {
+            return guard;
+        }
+        mutex.waiters.fetch_add(1, Ordering::Relaxed);
+        // SAFETY: the lock is initialized and pinned.
+        unsafe { libc::os_unfair_lock_lock(mutex.lock.get()) };
+        mutex.waiters.fetch_sub(1, Ordering::Relaxed);
+        PinnedMutexGuard {
+            mutex,
+            _not_send: PhantomData,
+        }
+    }
+
+    /// Always succeeds. os_unfair_lock_lock returns nothing and
+    /// aborts the process on misuse, such as recursive locking,
+    /// rather than reporting an error. Exists so code written against
+    /// every backend's `lock_checked` builds on macOS.
+    #[cfg_attr(docsrs, doc(cfg(feature = "fallible")))]
+    #[cfg(feature = "fallible")]
+    pub fn lock_checked(self: Pin<&Self>) -> Result<PinnedMutexGuard<'_, T>, LockError> {
+        Ok(self.lock())
+    }
+
+    /// Returns an estimate of how many threads are blocked waiting
+    /// for the lock. It changes concurrently, so treat it as a hint.
+    pub fn waiters(&self) -> usize {
+        self.waiters.load(Ordering::Relaxed)
+    }
+
+    /// Returns true if some thread appears to be blocked waiting for
+    /// the lock. Adaptive code can, for example, do more work per
+    /// acquisition while the lock is contended.
+    pub fn is_contended(&self) -> bool {
+        self.waiters() > 0
+    }
+
+    /// In debug builds, crashes the process unless the current
+    /// thread holds the lock. Compiles to nothing in release builds.
+    pub fn debug_assert_owner(self: Pin<&Self>) {
+        #[cfg(debug_assertions)]
+        unsafe {
+            libc::os_unfair_lock_assert_owner(self.lock.get())
+        }
+    }
+
+    /// In debug builds, crashes the process if the current thread
+    /// holds the lock. Compiles to nothing in release builds.
+    pub fn debug_assert_not_owner(self: Pin<&Self>) {
+        #[cfg(debug_assertions)]
+        unsafe {
+            libc::os_unfair_lock_assert_not_owner(self.lock.get())
+        }
+    }
+
+    fn try_lock_inner(&self) -> Option<PinnedMutexGuard<'_, T>> {
+        // SAFETY: the lock is initialized, and the guard's borrow keeps
+        // it from moving until it is unlocked.
+        if unsafe { libc::os_unfair_lock_trylock(self.lock.get()) } {
+            Some(PinnedMutexGuard {
+                mutex: self,
+                _not_send: PhantomData,
+            })
+        } else {
+            None
+        }
+    }
+}
+
+impl<T: Default> Default for PinnedMutex<T> {
+    fn default() -> Self {
+        Self::new(Default::default())
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for PinnedMutex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("PinnedMutex");
+        match self.try_lock_inner() {
+            Some(guard) => d.field("data", &&*guard),
+            None => d.field("data", &format_args!("<locked>")),
+        };
+        d.finish_non_exhaustive()
+    }
+}
+
+/// Provides access to mutex's contents. [Deref] to `&T` is always
+/// possible. [DerefMut] to `&mut T` is only possive if T is `Unpin`.
+///
+/// `as_ref` and `as_mut` project structural pinning.
+pub struct PinnedMutexGuard<'a, T: 'a> {
+    mutex: &'a PinnedMutex<T>,
+    _not_send: PhantomData<*mut ()>,
+}
+
+// SAFETY: the guard only hands out &T to other threads. It is not
+// Send because os_unfair_lock must be unlocked by its owner.
+unsafe impl<'a, T: Sync> Sync for PinnedMutexGuard<'a, T> {}
+
+impl<'a, T> PinnedMutexGuard<'a, T> {
+    /// Provides pinned access to the underlying T.
+    pub fn as_ref(&self) -> Pin<&T> {
+        // PinnedMutex::lock requires the mutex is pinned.
+        unsafe { Pin::new_unchecked(&*self.mutex.data.get()) }
+    }
+
+    /// Provides pinned mutable access to the underlying T.
+    pub fn as_mut(&mut self) -> Pin<&mut T> {
+        // PinnedMutex::lock requires the mutex is pinned.
+        // &mut self guarantees as_ref() cannot alias.
+        unsafe { Pin::new_unchecked(&mut *self.mutex.data.get()) }
+    }
+}
+
+impl<'a, T> Deref for PinnedMutexGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        // SAFETY: the guard holds the lock.
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<'a, T: Unpin> DerefMut for PinnedMutexGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        // SAFETY: the guard holds the lock, and T is Unpin.
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<'a, T> Drop for PinnedMutexGuard<'a, T> {
+    fn drop(&mut self) {
+        // SAFETY: the guard holds the lock on this thread.
+        unsafe { libc::os_unfair_lock_unlock(self.mutex.lock.get()) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pin_project::pin_project;
+    use std::{marker::PhantomPinned, pin::pin};
+
+    #[test]
+    fn mutate_through_lock() {
+        let pm = pin!(PinnedMutex::new(15));
+        let mut locked = pm.as_ref().lock();
+        *locked = 16;
+    }
+
+    #[pin_project(UnsafeUnpin)]
+    struct MustPin {
+        value: u32,
+        pinned: PhantomPinned,
+    }
+
+    impl MustPin {
+        fn new() -> Self {
+            Self {
+                value: 0,
+                pinned: PhantomPinned,
+            }
+        }
+
+        fn inc(self: Pin<&mut Self>) -> u32 {
+            let value = self.project().value;
+            let prev = *value;
+            *value += 1;
+            prev
+        }
+
+        fn get(self: Pin<&Self>) -> u32 {
+            *self.project_ref().value
+        }
+    }
+
+    #[test]
+    fn pinned_method() {
+        let pm = pin!(PinnedMutex::new(MustPin::new()));
+        let mut locked = pm.as_ref().lock();
+        assert_eq!(0, locked.as_mut().inc());
+        assert_eq!(1, locked.as_mut().inc());
+        assert_eq!(2, locked.as_ref().get());
+    }
+
+    #[test]
+    fn owner_assertions() {
+        let pm = pin!(PinnedMutex::new(0));
+        pm.as_ref().debug_assert_not_owner();
+        let _locked = pm.as_ref().lock();
+        pm.as_ref().debug_assert_owner();
+    }
+
+    #[test]
+    fn contention_hint() {
+        let pm = pin!(PinnedMutex::new(0));
+        let pm = pm.as_ref();
+        assert!(!pm.is_contended());
+        let guard = pm.lock();
+        std::thread::scope(|s| {
+            s.spawn(|| *pm.lock() += 1);
+            while !pm.is_contended() {
+                std::thread::yield_now();
+            }
+            assert_eq!(1, pm.waiters());
+            drop(guard);
+        });
+        assert!(!pm.is_contended());
+        assert_eq!(1, *pm.lock());
+    }
+}
@@ -1,104 +1,2257 @@
-use parking_lot::{Condvar, Mutex, MutexGuard};
+use parking_lot::{
+    FairMutex, FairMutexGuard, MappedFairMutexGuard, MappedMutexGuard, MappedRwLockReadGuard,
+    MappedRwLockWriteGuard, Mutex, MutexGuard, ReentrantMutex, ReentrantMutexGuard, RwLock,
+    RwLockReadGuard, RwLockUpgradableReadGuard, RwLockWriteGuard,
+};
+use parking_lot_core::{
+    ParkResult, RequeueOp, UnparkToken, DEFAULT_PARK_TOKEN, DEFAULT_UNPARK_TOKEN,
+};
+use std::fmt;
+use std::marker::PhantomPinned;
+use std::mem::{self, ManuallyDrop};
 use std::ops::{Deref, DerefMut};
+use std::panic::{RefUnwindSafe, UnwindSafe};
 use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::instrument::{HoldTimer, Instrument};
+#[cfg(feature = "fallible")]
+use crate::LockError;
+use crate::{Deadline, NotifyPolicy};
 
 /// Provides [structural
 /// pinning](https://doc.rust-lang.org/std/pin/index.html#projections-and-structural-pinning)
 /// atop [Mutex].
 #[derive(Debug, Default)]
-pub struct PinnedMutex<T> {
-    inner: Mutex<T>,
+pub struct PinnedMutex<T> {
+    inner: Mutex<T>,
+    waiters: AtomicUsize,
+    instrument: Instrument,
+}
+
+// A panic while the lock is held cannot leave the mutex itself
+// broken, because the guard unlocks it during unwinding. Unlike
+// std::sync::Mutex, nothing poisons the contents, so code that
+// catches the panic sees whatever state the panicking holder left.
+// Without poisoning to flag that, unwind safety has to come from T.
+impl<T: UnwindSafe> UnwindSafe for PinnedMutex<T> {}
+impl<T: RefUnwindSafe> RefUnwindSafe for PinnedMutex<T> {}
+
+impl<T> PinnedMutex<T> {
+    /// Creates an unlocked mutex. It is `const`, so a mutex can be a
+    /// `static` and locked through [pin_static](Self::pin_static).
+    pub const fn new(init: T) -> Self {
+        Self {
+            inner: Mutex::new(init),
+            waiters: AtomicUsize::new(0),
+            instrument: Instrument::new(),
+        }
+    }
+
+    /// Names this mutex for instrumentation. Only named mutexes are
+    /// instrumented, and without an instrumentation feature, the name
+    /// is ignored.
+    ///
+    /// With the `metrics` feature, named mutexes record
+    /// `pinned_mutex.acquire.wait_ns` and `pinned_mutex.hold_ns`
+    /// histograms labeled with `lock = name`. With the `tracy`
+    /// feature, they appear in Tracy's lock view under `name`.
+    pub fn named(mut self, name: &'static str) -> Self {
+        self.instrument = Instrument::named(name);
+        self
+    }
+
+    /// Pins a mutex that is never deallocated, such as a `static`.
+    /// Nothing can move out of a `'static` shared reference, so no
+    /// unsafe code or boxing is needed.
+    pub fn pin_static(&'static self) -> Pin<&'static Self> {
+        Pin::static_ref(self)
+    }
+
+    /// Acquires the lock and returns a guard.
+    ///
+    /// [parking_lot] does not support poisoning. Neither does this.
+    pub fn lock(self: Pin<&Self>) -> PinnedMutexGuard<'_, T> {
+        let mutex = self.get_ref();
+        let timer = mutex.instrument.start_acquire();
+        let guard = match mutex.inner.try_lock() {
+            Some(guard) => guard,
+            None => {
+                mutex.waiters.fetch_add(1, Ordering::Relaxed);
+                let guard = mutex.inner.lock();
+                mutex.waiters.fetch_sub(1, Ordering::Relaxed);
+                guard
+            }
+        };
+        PinnedMutexGuard {
+            guard,
+            mutex: self,
+            hold: timer.acquired(),
+        }
+    }
+
+    /// Always succeeds, because parking_lot has no poisoning and its
+    /// lock cannot report an error. Exists so code written against
+    /// every backend's `lock_checked` can use parking_lot unchanged.
+    #[cfg_attr(docsrs, doc(cfg(feature = "fallible")))]
+    #[cfg(feature = "fallible")]
+    pub fn lock_checked(self: Pin<&Self>) -> Result<PinnedMutexGuard<'_, T>, LockError> {
+        Ok(self.lock())
+    }
+
+    /// Returns an estimate of how many threads are blocked waiting
+    /// for the lock. It changes concurrently, so treat it as a hint.
+    pub fn waiters(&self) -> usize {
+        self.waiters.load(Ordering::Relaxed)
+    }
+
+    /// Returns true if some thread appears to be blocked waiting for
+    /// the lock. Adaptive code can, for example, do more work per
+    /// acquisition while the lock is contended.
+    pub fn is_contended(&self) -> bool {
+        self.waiters() > 0
+    }
+
+    /// The parking key on which [PinnedCondvar::notify_all] lines up
+    /// waiters for this mutex. Nothing else parks on the address of
+    /// the waiter count.
+    fn relay_key(&self) -> usize {
+        &self.waiters as *const AtomicUsize as usize
+    }
+
+    /// Returns true if the lock is currently held. By the time the
+    /// caller looks, another thread may have acquired or released it,
+    /// so it is only suitable for monitoring, never for deciding
+    /// whether to lock.
+    pub fn is_locked(&self) -> bool {
+        self.inner.is_locked()
+    }
+
+    /// Returns a [PinnedMutexView] that locks this mutex but only
+    /// exposes the part of its contents selected by `project`.
+    pub fn view<U>(
+        self: Pin<&Self>,
+        project: fn(Pin<&mut T>) -> Pin<&mut U>,
+    ) -> PinnedMutexView<'_, T, U> {
+        PinnedMutexView {
+            mutex: self,
+            project,
+        }
+    }
+
+    /// Attempts to acquire the lock without blocking. Returns `None`
+    /// if another guard holds it.
+    pub fn try_lock(self: Pin<&Self>) -> Option<PinnedMutexGuard<'_, T>> {
+        let guard = self.get_ref().inner.try_lock()?;
+        Some(PinnedMutexGuard {
+            guard,
+            mutex: self,
+            hold: HoldTimer::start(&self.get_ref().instrument),
+        })
+    }
+
+    /// Attempts to acquire the lock, blocking for at most `timeout`.
+    /// Returns `None` if the timeout passed first.
+    pub fn try_lock_for(self: Pin<&Self>, timeout: Duration) -> Option<PinnedMutexGuard<'_, T>> {
+        self.try_lock_deadline(timeout)
+    }
+
+    /// Attempts to acquire the lock, blocking until at most
+    /// `deadline`. Returns `None` if the deadline passed first.
+    pub fn try_lock_until(self: Pin<&Self>, deadline: Instant) -> Option<PinnedMutexGuard<'_, T>> {
+        self.try_lock_deadline(deadline)
+    }
+
+    /// Returns a raw pointer to the contents, which may be handed to C
+    /// code. The contents are pinned, so the pointer stays valid for
+    /// as long as the mutex lives.
+    ///
+    /// Dereferencing it is only sound while the lock is held, such as
+    /// under a guard forgotten until [force_unlock](Self::force_unlock).
+    /// The contents are structurally pinned, so they must not be
+    /// moved through it either.
+    pub fn data_ptr(self: Pin<&Self>) -> *mut T {
+        self.get_ref().inner.data_ptr()
+    }
+
+    /// Releases the lock without a guard, for FFI code that forgets
+    /// a guard across a C callback and unlocks once control returns.
+    /// Instrumentation does not observe the release.
+    ///
+    /// # Safety
+    ///
+    /// The current context must logically own the lock through a
+    /// [PinnedMutexGuard] that was discarded with [mem::forget].
+    pub unsafe fn force_unlock(self: Pin<&Self>) {
+        // SAFETY: the caller owns the lock.
+        unsafe { self.get_ref().inner.force_unlock() }
+    }
+
+    /// Like [force_unlock](Self::force_unlock), but uses a fair
+    /// unlock, handing the lock directly to a waiting thread if there
+    /// is one.
+    ///
+    /// # Safety
+    ///
+    /// As for [force_unlock](Self::force_unlock).
+    pub unsafe fn force_unlock_fair(self: Pin<&Self>) {
+        // SAFETY: the caller owns the lock.
+        unsafe { self.get_ref().inner.force_unlock_fair() }
+    }
+
+    /// Attempts to acquire the lock, blocking until at most
+    /// `deadline`, which may be a [Duration], an [Instant], or
+    /// [Deadline::Never]. Returns `None` if the deadline passed first.
+    /// A timed lock is instrumented like a try_lock.
+    pub fn try_lock_deadline(
+        self: Pin<&Self>,
+        deadline: impl Into<Deadline>,
+    ) -> Option<PinnedMutexGuard<'_, T>> {
+        let at = match deadline.into().to_instant() {
+            Some(at) => at,
+            None => return Some(self.lock()),
+        };
+        let mutex = self.get_ref();
+        let guard = match mutex.inner.try_lock() {
+            Some(guard) => guard,
+            None => {
+                mutex.waiters.fetch_add(1, Ordering::Relaxed);
+                let guard = mutex.inner.try_lock_until(at);
+                mutex.waiters.fetch_sub(1, Ordering::Relaxed);
+                guard?
+            }
+        };
+        Some(PinnedMutexGuard {
+            guard,
+            mutex: self,
+            hold: HoldTimer::start(&mutex.instrument),
+        })
+    }
+}
+
+impl<T: Clone> PinnedMutex<T> {
+    /// Locks briefly and returns a copy of the contents, for readers
+    /// that only need a snapshot.
+    pub fn get_cloned(self: Pin<&Self>) -> T {
+        self.lock().clone()
+    }
+}
+
+/// Locks the source and clones its contents into a new, unlocked,
+/// unnamed mutex.
+impl<T: Clone> Clone for PinnedMutex<T> {
+    fn clone(&self) -> Self {
+        // Lock inner directly, bypassing the instrumentation. Cloning
+        // does not require pinning the source.
+        Self::new(self.inner.lock().clone())
+    }
+}
+
+/// Compares contents, locking both mutexes. To avoid deadlocking
+/// with a concurrent comparison in the other direction, the mutex at
+/// the lower address is always locked first. Comparing a mutex with
+/// itself locks it once.
+impl<T: PartialEq> PartialEq for PinnedMutex<T> {
+    fn eq(&self, other: &Self) -> bool {
+        // Lock inner directly, bypassing the instrumentation.
+        // Comparison does not require pinning.
+        if std::ptr::eq(self, other) {
+            let guard = self.inner.lock();
+            // T's PartialEq need not be reflexive, as with NaN.
+            #[allow(clippy::eq_op)]
+            return *guard == *guard;
+        }
+        let (first, second) = if (self as *const Self) < (other as *const Self) {
+            (self, other)
+        } else {
+            (other, self)
+        };
+        let first = first.inner.lock();
+        let second = second.inner.lock();
+        *first == *second
+    }
+}
+
+impl<T: Eq> Eq for PinnedMutex<T> {}
+
+impl<T: 'static> PinnedMutex<T> {
+    /// Like [lock](Self::lock), but the guard holds a reference count
+    /// instead of a borrow, so it is `'static` and can be stashed in
+    /// futures or callbacks.
+    pub fn lock_arc(self: Pin<Arc<Self>>) -> ArcPinnedMutexGuard<T> {
+        // SAFETY: the guard holds the Arc until after the borrowed
+        // guard is dropped.
+        let mutex = unsafe { crate::pin_arc_static(&self) };
+        ArcPinnedMutexGuard {
+            guard: mutex.lock(),
+            _mutex: self,
+        }
+    }
+
+    /// Like [try_lock](Self::try_lock), but returns an owned guard as
+    /// in [lock_arc](Self::lock_arc).
+    pub fn try_lock_arc(self: Pin<Arc<Self>>) -> Option<ArcPinnedMutexGuard<T>> {
+        // SAFETY: as in lock_arc.
+        let mutex = unsafe { crate::pin_arc_static(&self) };
+        Some(ArcPinnedMutexGuard {
+            guard: mutex.try_lock()?,
+            _mutex: self,
+        })
+    }
+}
+
+impl<T> From<T> for PinnedMutex<T> {
+    fn from(init: T) -> Self {
+        Self::new(init)
+    }
+}
+
+impl<T: Unpin> PinnedMutex<T> {
+    /// Swaps the contents of two mutexes.
+    ///
+    /// Both locks are taken in address order, so concurrent swaps of
+    /// the same pair in opposite directions cannot deadlock. Swapping
+    /// a mutex with itself does nothing.
+    pub fn swap(self: Pin<&Self>, other: Pin<&Self>) {
+        if std::ptr::eq(self.get_ref(), other.get_ref()) {
+            return;
+        }
+        let (first, second) = if (self.get_ref() as *const Self) < other.get_ref() {
+            (self, other)
+        } else {
+            (other, self)
+        };
+        let mut a = first.lock();
+        let mut b = second.lock();
+        std::mem::swap(&mut *a, &mut *b);
+    }
+
+    /// Swaps the contents of two mutexes if both can be locked without
+    /// blocking. Returns whether the swap happened. Swapping a mutex
+    /// with itself does nothing and succeeds.
+    pub fn try_swap(self: Pin<&Self>, other: Pin<&Self>) -> bool {
+        if std::ptr::eq(self.get_ref(), other.get_ref()) {
+            return true;
+        }
+        match (self.try_lock(), other.try_lock()) {
+            (Some(mut a), Some(mut b)) => {
+                std::mem::swap(&mut *a, &mut *b);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Provides access to mutex's contents. [Deref] to `&T` is always
+/// possible. [DerefMut] to `&mut T` is only possive if T is `Unpin`.
+///
+/// `as_ref` and `as_mut` project structural pinning.
+///
+/// With the `send_guard` feature, guards from this module are `Send`,
+/// so a lock may be released on a different thread than the one that
+/// acquired it. `send_guard` cannot be combined with
+/// `deadlock_detection`.
+pub struct PinnedMutexGuard<'a, T: 'a> {
+    guard: MutexGuard<'a, T>,
+    mutex: Pin<&'a PinnedMutex<T>>,
+    hold: HoldTimer<'a>,
+}
+
+impl<'a, T> PinnedMutexGuard<'a, T> {
+    /// Provides pinned access to the underlying T.
+    pub fn as_ref(&self) -> Pin<&T> {
+        // PinnedMutex::lock requires the mutex is pinned.
+        unsafe { Pin::new_unchecked(&self.guard) }
+    }
+
+    /// Provides pinned mutable access to the underlying T.
+    pub fn as_mut(&mut self) -> Pin<&mut T> {
+        // PinnedMutex::lock requires the mutex is pinned.
+        // &mut self guarantees as_ref() cannot alias.
+        unsafe { Pin::new_unchecked(&mut self.guard) }
+    }
+
+    /// Returns the mutex this guard locks, so the guard can be dropped
+    /// and the same mutex locked again later.
+    pub fn mutex(&self) -> Pin<&'a PinnedMutex<T>> {
+        self.mutex
+    }
+
+    /// Releases the lock. Equivalent to dropping the guard, but says
+    /// so explicitly.
+    pub fn unlock(self) {
+        drop(self)
+    }
+
+    /// Releases the lock fairly, handing it directly to a waiting
+    /// thread if there is one. Fair handoff is slower than a normal
+    /// unlock but keeps waiters from starving.
+    pub fn unlock_fair(self) {
+        let PinnedMutexGuard { guard, hold, .. } = self;
+        MutexGuard::unlock_fair(guard);
+        hold.finish();
+    }
+
+    /// Temporarily releases the lock to run `f`, such as slow code in
+    /// the middle of a critical section, and reacquires it before
+    /// returning. The contents are pinned, so they stay put while
+    /// other threads hold the lock.
+    pub fn unlocked<U>(&mut self, f: impl FnOnce() -> U) -> U {
+        let hold = &mut self.hold;
+        let result = MutexGuard::unlocked(&mut self.guard, || {
+            hold.pause();
+            f()
+        });
+        self.hold.resume();
+        result
+    }
+
+    /// Like [unlocked](Self::unlocked), but releases the lock fairly,
+    /// as in [unlock_fair](Self::unlock_fair).
+    pub fn unlocked_fair<U>(&mut self, f: impl FnOnce() -> U) -> U {
+        let hold = &mut self.hold;
+        let result = MutexGuard::unlocked_fair(&mut self.guard, || {
+            hold.pause();
+            f()
+        });
+        self.hold.resume();
+        result
+    }
+
+    /// Hands the lock to a waiting thread, if there is one, and
+    /// reacquires it, so long-running holders can let waiters in
+    /// between steps. The contents are pinned, so they keep their
+    /// address while other threads hold the lock.
+    pub fn bump(&mut self) {
+        MutexGuard::bump(&mut self.guard)
+    }
+
+    /// Narrows the guard to the part of the contents selected by `f`,
+    /// keeping the lock held.
+    pub fn map<U: ?Sized>(
+        self,
+        f: impl FnOnce(Pin<&mut T>) -> Pin<&mut U>,
+    ) -> MappedPinnedMutexGuard<'a, U> {
+        let PinnedMutexGuard { guard, hold, .. } = self;
+        let guard = MutexGuard::map(guard, |data| {
+            // SAFETY: PinnedMutex::lock requires the mutex is pinned,
+            // and the mapped guard only hands the projection out
+            // pinned.
+            unsafe { f(Pin::new_unchecked(data)).get_unchecked_mut() }
+        });
+        MappedPinnedMutexGuard { guard, _hold: hold }
+    }
+
+    /// Like [map](Self::map), but `f` may decline, in which case the
+    /// original guard is returned.
+    pub fn try_map<U: ?Sized>(
+        self,
+        f: impl FnOnce(Pin<&mut T>) -> Option<Pin<&mut U>>,
+    ) -> Result<MappedPinnedMutexGuard<'a, U>, Self> {
+        let PinnedMutexGuard { guard, mutex, hold } = self;
+        let mapped = MutexGuard::try_map(guard, |data| {
+            // SAFETY: as in map.
+            unsafe { f(Pin::new_unchecked(data)).map(|u| u.get_unchecked_mut()) }
+        });
+        match mapped {
+            Ok(guard) => Ok(MappedPinnedMutexGuard { guard, _hold: hold }),
+            Err(guard) => Err(PinnedMutexGuard { guard, mutex, hold }),
+        }
+    }
+
+    /// Consumes the guard without releasing the lock, which stays held
+    /// for the rest of `'a`. Useful when a lock is held for a
+    /// subsystem's entire lifetime.
+    pub fn leak(self) -> Pin<&'a mut T> {
+        let PinnedMutexGuard { guard, hold, .. } = self;
+        hold.leak();
+        // SAFETY: PinnedMutex::lock requires the mutex is pinned.
+        unsafe { Pin::new_unchecked(MutexGuard::leak(guard)) }
+    }
+}
+
+impl<'a, T> Deref for PinnedMutexGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl<'a, T: Unpin> DerefMut for PinnedMutexGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.guard
+    }
+}
+
+impl<'a, T: fmt::Debug> fmt::Debug for PinnedMutexGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<'a, T: fmt::Display> fmt::Display for PinnedMutexGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+/// A [PinnedMutexGuard] narrowed to part of the contents by
+/// [map](PinnedMutexGuard::map) or [PinnedMutexView::lock]. [Deref]
+/// to `&U` is always possible. [DerefMut] to `&mut U` is only
+/// possible if U is `Unpin`.
+///
+/// `as_ref` and `as_mut` project structural pinning.
+pub struct MappedPinnedMutexGuard<'a, U: ?Sized + 'a> {
+    guard: MappedMutexGuard<'a, U>,
+    /// Records the hold when dropped, after the lock is released.
+    _hold: HoldTimer<'a>,
+}
+
+impl<'a, U: ?Sized> MappedPinnedMutexGuard<'a, U> {
+    /// Provides pinned access to the projected U.
+    pub fn as_ref(&self) -> Pin<&U> {
+        // SAFETY: map pinned the projection.
+        unsafe { Pin::new_unchecked(&self.guard) }
+    }
+
+    /// Provides pinned mutable access to the projected U.
+    pub fn as_mut(&mut self) -> Pin<&mut U> {
+        // SAFETY: as in as_ref. &mut self guarantees as_ref() cannot
+        // alias.
+        unsafe { Pin::new_unchecked(&mut self.guard) }
+    }
+}
+
+impl<'a, U: ?Sized> Deref for MappedPinnedMutexGuard<'a, U> {
+    type Target = U;
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl<'a, U: ?Sized + Unpin> DerefMut for MappedPinnedMutexGuard<'a, U> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.guard
+    }
+}
+
+impl<'a, U: ?Sized + fmt::Debug> fmt::Debug for MappedPinnedMutexGuard<'a, U> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<'a, U: ?Sized + fmt::Display> fmt::Display for MappedPinnedMutexGuard<'a, U> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+/// A [PinnedMutexGuard] that keeps its mutex alive, returned by
+/// [PinnedMutex::lock_arc].
+pub struct ArcPinnedMutexGuard<T: 'static> {
+    /// Borrows from `_mutex`, so it is declared first to drop first.
+    guard: PinnedMutexGuard<'static, T>,
+    _mutex: Pin<Arc<PinnedMutex<T>>>,
+}
+
+impl<T> ArcPinnedMutexGuard<T> {
+    /// Provides pinned access to the underlying T.
+    pub fn as_ref(&self) -> Pin<&T> {
+        self.guard.as_ref()
+    }
+
+    /// Provides pinned mutable access to the underlying T.
+    pub fn as_mut(&mut self) -> Pin<&mut T> {
+        self.guard.as_mut()
+    }
+}
+
+impl<T> Deref for ArcPinnedMutexGuard<T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl<T: Unpin> DerefMut for ArcPinnedMutexGuard<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.guard
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for ArcPinnedMutexGuard<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for ArcPinnedMutexGuard<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+/// A lock handle over one projected part of a [PinnedMutex]'s
+/// contents, created by [PinnedMutex::view].
+///
+/// Locking the view locks the whole parent mutex, but the guard only
+/// exposes the projected `U`. Views are `Copy`, so they can be handed
+/// to subsystems as a capability for exactly that part of the state.
+pub struct PinnedMutexView<'a, T, U> {
+    mutex: Pin<&'a PinnedMutex<T>>,
+    project: fn(Pin<&mut T>) -> Pin<&mut U>,
+}
+
+impl<'a, T, U> Clone for PinnedMutexView<'a, T, U> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, T, U> Copy for PinnedMutexView<'a, T, U> {}
+
+impl<'a, T, U> PinnedMutexView<'a, T, U> {
+    /// Locks the parent mutex and returns a guard over the projected
+    /// part.
+    pub fn lock(&self) -> MappedPinnedMutexGuard<'a, U> {
+        self.mutex.lock().map(self.project)
+    }
+}
+
+impl<T: Send> PinnedMutex<T> {
+    /// Runs `f` inside [thread::scope], passing a [PinnedMutexScope]
+    /// that spawns workers sharing this mutex.
+    pub fn scope<'env, R>(
+        self: Pin<&'env Self>,
+        f: impl for<'scope> FnOnce(PinnedMutexScope<'scope, 'env, T>) -> R,
+    ) -> R {
+        thread::scope(|scope| f(PinnedMutexScope { scope, mutex: self }))
+    }
+}
+
+/// A [thread::Scope] paired with a pinned mutex, created by
+/// [PinnedMutex::scope]. It is `Copy`, and each spawned worker
+/// receives its own copy, so no pinned reference needs to be captured
+/// by hand.
+pub struct PinnedMutexScope<'scope, 'env, T> {
+    scope: &'scope thread::Scope<'scope, 'env>,
+    mutex: Pin<&'env PinnedMutex<T>>,
+}
+
+impl<'scope, 'env, T> Clone for PinnedMutexScope<'scope, 'env, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'scope, 'env, T> Copy for PinnedMutexScope<'scope, 'env, T> {}
+
+impl<'scope, 'env, T: Send> PinnedMutexScope<'scope, 'env, T> {
+    /// Spawns a scoped thread that receives a copy of this scope.
+    pub fn spawn<F, R>(&self, f: F) -> thread::ScopedJoinHandle<'scope, R>
+    where
+        F: FnOnce(Self) -> R + Send + 'scope,
+        R: Send + 'scope,
+    {
+        let this = *self;
+        self.scope.spawn(move || f(this))
+    }
+
+    /// Returns the shared mutex.
+    pub fn mutex(&self) -> Pin<&'env PinnedMutex<T>> {
+        self.mutex
+    }
+
+    /// Locks the shared mutex.
+    pub fn lock(&self) -> PinnedMutexGuard<'env, T> {
+        self.mutex.lock()
+    }
+
+    /// Locks the shared mutex, runs `f` with pinned access to the
+    /// contents, and unlocks it.
+    pub fn with_lock<R>(&self, f: impl FnOnce(Pin<&mut T>) -> R) -> R {
+        f(self.lock().as_mut())
+    }
+
+    /// Returns the underlying [thread::Scope].
+    pub fn thread_scope(&self) -> &'scope thread::Scope<'scope, 'env> {
+        self.scope
+    }
+}
+
+/// Provides [structural
+/// pinning](https://doc.rust-lang.org/std/pin/index.html#projections-and-structural-pinning)
+/// atop [FairMutex], which always hands the lock to the
+/// longest-waiting thread. It trades throughput for bounded waits in
+/// latency-sensitive pipelines; prefer [PinnedMutex] otherwise.
+#[derive(Debug, Default)]
+pub struct PinnedFairMutex<T> {
+    inner: FairMutex<T>,
+}
+
+impl<T> PinnedFairMutex<T> {
+    pub const fn new(init: T) -> Self {
+        Self {
+            inner: FairMutex::new(init),
+        }
+    }
+
+    /// Acquires the lock and returns a guard.
+    pub fn lock(self: Pin<&Self>) -> PinnedFairMutexGuard<'_, T> {
+        PinnedFairMutexGuard {
+            guard: self.get_ref().inner.lock(),
+        }
+    }
+
+    /// Always succeeds, as [PinnedMutex::lock_checked] does.
+    #[cfg_attr(docsrs, doc(cfg(feature = "fallible")))]
+    #[cfg(feature = "fallible")]
+    pub fn lock_checked(self: Pin<&Self>) -> Result<PinnedFairMutexGuard<'_, T>, LockError> {
+        Ok(self.lock())
+    }
+
+    /// Attempts to acquire the lock without blocking. Returns `None`
+    /// if another guard holds it.
+    pub fn try_lock(self: Pin<&Self>) -> Option<PinnedFairMutexGuard<'_, T>> {
+        Some(PinnedFairMutexGuard {
+            guard: self.get_ref().inner.try_lock()?,
+        })
+    }
+
+    /// Attempts to acquire the lock, blocking for at most `timeout`.
+    pub fn try_lock_for(
+        self: Pin<&Self>,
+        timeout: Duration,
+    ) -> Option<PinnedFairMutexGuard<'_, T>> {
+        Some(PinnedFairMutexGuard {
+            guard: self.get_ref().inner.try_lock_for(timeout)?,
+        })
+    }
+
+    /// Attempts to acquire the lock, blocking until at most
+    /// `deadline`.
+    pub fn try_lock_until(
+        self: Pin<&Self>,
+        deadline: Instant,
+    ) -> Option<PinnedFairMutexGuard<'_, T>> {
+        Some(PinnedFairMutexGuard {
+            guard: self.get_ref().inner.try_lock_until(deadline)?,
+        })
+    }
+
+    /// Attempts to acquire the lock, blocking until at most
+    /// `deadline`, which may be a [Duration], an [Instant], or
+    /// [Deadline::Never].
+    pub fn try_lock_deadline(
+        self: Pin<&Self>,
+        deadline: impl Into<Deadline>,
+    ) -> Option<PinnedFairMutexGuard<'_, T>> {
+        match deadline.into().to_instant() {
+            Some(at) => self.try_lock_until(at),
+            None => Some(self.lock()),
+        }
+    }
+
+    /// Returns true if the lock is currently held. The answer may be
+    /// stale by the time the caller looks, so only use it for
+    /// monitoring.
+    pub fn is_locked(&self) -> bool {
+        self.inner.is_locked()
+    }
+}
+
+impl<T> From<T> for PinnedFairMutex<T> {
+    fn from(init: T) -> Self {
+        Self::new(init)
+    }
+}
+
+/// Provides access to a [PinnedFairMutex]'s contents. [Deref] to `&T`
+/// is always possible. [DerefMut] to `&mut T` is only possible if T
+/// is `Unpin`. Dropping the guard unlocks fairly.
+///
+/// `as_ref` and `as_mut` project structural pinning.
+pub struct PinnedFairMutexGuard<'a, T: 'a> {
+    guard: FairMutexGuard<'a, T>,
+}
+
+impl<'a, T> PinnedFairMutexGuard<'a, T> {
+    /// Provides pinned access to the underlying T.
+    pub fn as_ref(&self) -> Pin<&T> {
+        // PinnedFairMutex::lock requires the mutex is pinned.
+        unsafe { Pin::new_unchecked(&self.guard) }
+    }
+
+    /// Provides pinned mutable access to the underlying T.
+    pub fn as_mut(&mut self) -> Pin<&mut T> {
+        // PinnedFairMutex::lock requires the mutex is pinned.
+        // &mut self guarantees as_ref() cannot alias.
+        unsafe { Pin::new_unchecked(&mut self.guard) }
+    }
+
+    /// Narrows the guard to the part of the contents selected by `f`,
+    /// keeping the lock held.
+    pub fn map<U: ?Sized>(
+        self,
+        f: impl FnOnce(Pin<&mut T>) -> Pin<&mut U>,
+    ) -> MappedPinnedFairMutexGuard<'a, U> {
+        let guard = FairMutexGuard::map(self.guard, |data| {
+            // SAFETY: PinnedFairMutex::lock requires the mutex is
+            // pinned, and the mapped guard only hands the projection
+            // out pinned.
+            unsafe { f(Pin::new_unchecked(data)).get_unchecked_mut() }
+        });
+        MappedPinnedFairMutexGuard { guard }
+    }
+
+    /// Temporarily releases the lock to run `f`, and reacquires it
+    /// before returning. The contents stay put meanwhile.
+    pub fn unlocked<U>(&mut self, f: impl FnOnce() -> U) -> U {
+        FairMutexGuard::unlocked(&mut self.guard, f)
+    }
+
+    /// Hands the lock to a waiting thread, if there is one, and
+    /// reacquires it.
+    pub fn bump(&mut self) {
+        FairMutexGuard::bump(&mut self.guard)
+    }
+}
+
+impl<'a, T> Deref for PinnedFairMutexGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl<'a, T: Unpin> DerefMut for PinnedFairMutexGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.guard
+    }
+}
+
+impl<'a, T: fmt::Debug> fmt::Debug for PinnedFairMutexGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<'a, T: fmt::Display> fmt::Display for PinnedFairMutexGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+/// A [PinnedFairMutexGuard] narrowed to part of the contents by
+/// [map](PinnedFairMutexGuard::map). [Deref] to `&U` is always
+/// possible. [DerefMut] to `&mut U` is only possible if U is `Unpin`.
+///
+/// `as_ref` and `as_mut` project structural pinning.
+pub struct MappedPinnedFairMutexGuard<'a, U: ?Sized + 'a> {
+    guard: MappedFairMutexGuard<'a, U>,
+}
+
+impl<'a, U: ?Sized> MappedPinnedFairMutexGuard<'a, U> {
+    /// Provides pinned access to the projected U.
+    pub fn as_ref(&self) -> Pin<&U> {
+        // SAFETY: map pinned the projection.
+        unsafe { Pin::new_unchecked(&self.guard) }
+    }
+
+    /// Provides pinned mutable access to the projected U.
+    pub fn as_mut(&mut self) -> Pin<&mut U> {
+        // SAFETY: as in as_ref. &mut self guarantees as_ref() cannot
+        // alias.
+        unsafe { Pin::new_unchecked(&mut self.guard) }
+    }
+}
+
+impl<'a, U: ?Sized> Deref for MappedPinnedFairMutexGuard<'a, U> {
+    type Target = U;
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl<'a, U: ?Sized + Unpin> DerefMut for MappedPinnedFairMutexGuard<'a, U> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.guard
+    }
+}
+
+impl<'a, U: ?Sized + fmt::Debug> fmt::Debug for MappedPinnedFairMutexGuard<'a, U> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<'a, U: ?Sized + fmt::Display> fmt::Display for MappedPinnedFairMutexGuard<'a, U> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+/// Provides [structural
+/// pinning](https://doc.rust-lang.org/std/pin/index.html#projections-and-structural-pinning)
+/// atop [ReentrantMutex], which the thread holding it may acquire
+/// again, as in callbacks that reenter the code that invoked them.
+///
+/// Several guards on one thread may be alive at once, so guards only
+/// provide shared access: [Deref] to `&T` and pinned `as_ref`. Use
+/// interior mutability, like [Cell](std::cell::Cell) or
+/// [RefCell](std::cell::RefCell), to mutate the contents.
+#[derive(Debug, Default)]
+pub struct PinnedReentrantMutex<T> {
+    inner: ReentrantMutex<T>,
+}
+
+impl<T> PinnedReentrantMutex<T> {
+    pub const fn new(init: T) -> Self {
+        Self {
+            inner: ReentrantMutex::new(init),
+        }
+    }
+
+    /// Acquires the lock, blocking unless the current thread already
+    /// holds it, and returns a guard.
+    pub fn lock(self: Pin<&Self>) -> PinnedReentrantMutexGuard<'_, T> {
+        PinnedReentrantMutexGuard {
+            guard: self.get_ref().inner.lock(),
+        }
+    }
+
+    /// Always succeeds: there is no poisoning, and reacquiring the
+    /// lock on the thread that holds it cannot deadlock.
+    #[cfg_attr(docsrs, doc(cfg(feature = "fallible")))]
+    #[cfg(feature = "fallible")]
+    pub fn lock_checked(self: Pin<&Self>) -> Result<PinnedReentrantMutexGuard<'_, T>, LockError> {
+        Ok(self.lock())
+    }
+
+    /// Attempts to acquire the lock without blocking. Succeeds if the
+    /// lock is free or already held by the current thread.
+    pub fn try_lock(self: Pin<&Self>) -> Option<PinnedReentrantMutexGuard<'_, T>> {
+        Some(PinnedReentrantMutexGuard {
+            guard: self.get_ref().inner.try_lock()?,
+        })
+    }
+
+    /// Returns true if any thread holds the lock. The answer may be
+    /// stale by the time the caller looks, so only use it for
+    /// monitoring.
+    pub fn is_locked(&self) -> bool {
+        self.inner.is_locked()
+    }
+
+    /// Returns true if the current thread holds the lock.
+    pub fn is_owned_by_current_thread(&self) -> bool {
+        self.inner.is_owned_by_current_thread()
+    }
+}
+
+impl<T> From<T> for PinnedReentrantMutex<T> {
+    fn from(init: T) -> Self {
+        Self::new(init)
+    }
+}
+
+/// Provides shared access to a [PinnedReentrantMutex]'s contents.
+/// [Deref] to `&T` is always possible.
+///
+/// `as_ref` projects structural pinning.
+pub struct PinnedReentrantMutexGuard<'a, T: 'a> {
+    guard: ReentrantMutexGuard<'a, T>,
+}
+
+impl<'a, T> PinnedReentrantMutexGuard<'a, T> {
+    /// Provides pinned access to the underlying T.
+    pub fn as_ref(&self) -> Pin<&T> {
+        // PinnedReentrantMutex::lock requires the mutex is pinned.
+        unsafe { Pin::new_unchecked(&self.guard) }
+    }
+}
+
+impl<'a, T> Deref for PinnedReentrantMutexGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl<'a, T: fmt::Debug> fmt::Debug for PinnedReentrantMutexGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<'a, T: fmt::Display> fmt::Display for PinnedReentrantMutexGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+/// Provides [structural
+/// pinning](https://doc.rust-lang.org/std/pin/index.html#projections-and-structural-pinning)
+/// atop [RwLock], for pinned state that is read by many threads and
+/// written by few.
+#[derive(Debug, Default)]
+pub struct PinnedRwLock<T: ?Sized> {
+    inner: RwLock<T>,
+}
+
+impl<T> PinnedRwLock<T> {
+    pub const fn new(init: T) -> Self {
+        Self {
+            inner: RwLock::new(init),
+        }
+    }
+}
+
+impl<T: ?Sized> PinnedRwLock<T> {
+    /// Acquires a shared read lock and returns a guard.
+    pub fn read(self: Pin<&Self>) -> PinnedRwLockReadGuard<'_, T> {
+        PinnedRwLockReadGuard {
+            guard: self.get_ref().inner.read(),
+        }
+    }
+
+    /// Acquires an exclusive write lock and returns a guard.
+    pub fn write(self: Pin<&Self>) -> PinnedRwLockWriteGuard<'_, T> {
+        PinnedRwLockWriteGuard {
+            guard: self.get_ref().inner.write(),
+        }
+    }
+
+    /// Always succeeds, because there is no poisoning. Unlike
+    /// [PinnedMutex::lock_checked], this cannot detect a thread
+    /// reacquiring a lock it already holds.
+    #[cfg_attr(docsrs, doc(cfg(feature = "fallible")))]
+    #[cfg(feature = "fallible")]
+    pub fn read_checked(self: Pin<&Self>) -> Result<PinnedRwLockReadGuard<'_, T>, LockError> {
+        Ok(self.read())
+    }
+
+    /// Always succeeds, as [read_checked](Self::read_checked) does.
+    #[cfg_attr(docsrs, doc(cfg(feature = "fallible")))]
+    #[cfg(feature = "fallible")]
+    pub fn write_checked(self: Pin<&Self>) -> Result<PinnedRwLockWriteGuard<'_, T>, LockError> {
+        Ok(self.write())
+    }
+
+    /// Acquires an upgradable read lock, which shares the lock with
+    /// readers but excludes writers and other upgradable readers, so
+    /// it can later be [upgraded](PinnedRwLockUpgradableReadGuard::upgrade)
+    /// without another thread writing in between.
+    pub fn upgradable_read(self: Pin<&Self>) -> PinnedRwLockUpgradableReadGuard<'_, T> {
+        PinnedRwLockUpgradableReadGuard {
+            guard: self.get_ref().inner.upgradable_read(),
+        }
+    }
+
+    /// Acquires a shared read lock without queuing behind waiting
+    /// writers. A plain [read](Self::read) does queue, so a thread that
+    /// already holds a read lock would deadlock reacquiring it while a
+    /// writer waits. Recursive reads can starve writers, so use them
+    /// only where reentry is possible.
+    pub fn read_recursive(self: Pin<&Self>) -> PinnedRwLockReadGuard<'_, T> {
+        PinnedRwLockReadGuard {
+            guard: self.get_ref().inner.read_recursive(),
+        }
+    }
+
+    /// Like [read_recursive](Self::read_recursive), but returns `None`
+    /// instead of blocking if a writer holds the lock.
+    pub fn try_read_recursive(self: Pin<&Self>) -> Option<PinnedRwLockReadGuard<'_, T>> {
+        Some(PinnedRwLockReadGuard {
+            guard: self.get_ref().inner.try_read_recursive()?,
+        })
+    }
+
+    /// Attempts to acquire a shared read lock, blocking for at most
+    /// `timeout`. Returns `None` if the timeout passed first.
+    pub fn try_read_for(
+        self: Pin<&Self>,
+        timeout: Duration,
+    ) -> Option<PinnedRwLockReadGuard<'_, T>> {
+        Some(PinnedRwLockReadGuard {
+            guard: self.get_ref().inner.try_read_for(timeout)?,
+        })
+    }
+
+    /// Attempts to acquire a shared read lock, blocking until at most
+    /// `deadline`. Returns `None` if the deadline passed first.
+    pub fn try_read_until(
+        self: Pin<&Self>,
+        deadline: Instant,
+    ) -> Option<PinnedRwLockReadGuard<'_, T>> {
+        Some(PinnedRwLockReadGuard {
+            guard: self.get_ref().inner.try_read_until(deadline)?,
+        })
+    }
+
+    /// Attempts to acquire a shared read lock, blocking until at most
+    /// `deadline`, which may be a [Duration], an [Instant], or
+    /// [Deadline::Never]. Returns `None` if the deadline passed first.
+    pub fn try_read_deadline(
+        self: Pin<&Self>,
+        deadline: impl Into<Deadline>,
+    ) -> Option<PinnedRwLockReadGuard<'_, T>> {
+        match deadline.into().to_instant() {
+            Some(at) => self.try_read_until(at),
+            None => Some(self.read()),
+        }
+    }
+
+    /// Attempts to acquire an exclusive write lock, blocking for at
+    /// most `timeout`. Returns `None` if the timeout passed first.
+    pub fn try_write_for(
+        self: Pin<&Self>,
+        timeout: Duration,
+    ) -> Option<PinnedRwLockWriteGuard<'_, T>> {
+        Some(PinnedRwLockWriteGuard {
+            guard: self.get_ref().inner.try_write_for(timeout)?,
+        })
+    }
+
+    /// Attempts to acquire an exclusive write lock, blocking until at
+    /// most `deadline`. Returns `None` if the deadline passed first.
+    pub fn try_write_until(
+        self: Pin<&Self>,
+        deadline: Instant,
+    ) -> Option<PinnedRwLockWriteGuard<'_, T>> {
+        Some(PinnedRwLockWriteGuard {
+            guard: self.get_ref().inner.try_write_until(deadline)?,
+        })
+    }
+
+    /// Attempts to acquire an exclusive write lock, blocking until at
+    /// most `deadline`, as in
+    /// [try_read_deadline](Self::try_read_deadline).
+    pub fn try_write_deadline(
+        self: Pin<&Self>,
+        deadline: impl Into<Deadline>,
+    ) -> Option<PinnedRwLockWriteGuard<'_, T>> {
+        match deadline.into().to_instant() {
+            Some(at) => self.try_write_until(at),
+            None => Some(self.write()),
+        }
+    }
+
+    /// Returns true if any lock is currently held, shared or
+    /// exclusive. As with [PinnedMutex::is_locked], the answer may be
+    /// stale by the time the caller looks, so it is only suitable for
+    /// monitoring.
+    pub fn is_locked(&self) -> bool {
+        self.inner.is_locked()
+    }
+
+    /// Returns true if a write lock is currently held. Only suitable
+    /// for monitoring, as with [is_locked](Self::is_locked).
+    pub fn is_locked_exclusive(&self) -> bool {
+        self.inner.is_locked_exclusive()
+    }
+
+    /// Returns a raw pointer to the contents, which may be handed to C
+    /// code. The contents are pinned, so the pointer stays valid for
+    /// as long as the lock lives.
+    ///
+    /// Reading through it is only sound while a lock is held, and
+    /// writing only while a write lock is held. The contents are
+    /// structurally pinned, so they must not be moved through it
+    /// either.
+    pub fn data_ptr(self: Pin<&Self>) -> *mut T {
+        self.get_ref().inner.data_ptr()
+    }
+
+    /// Releases a read lock without a guard, for FFI code that forgets
+    /// a guard across a C callback and unlocks once control returns.
+    ///
+    /// # Safety
+    ///
+    /// The current context must logically own a read lock through a
+    /// [PinnedRwLockReadGuard] that was discarded with [mem::forget].
+    pub unsafe fn force_unlock_read(self: Pin<&Self>) {
+        // SAFETY: the caller owns a read lock.
+        unsafe { self.get_ref().inner.force_unlock_read() }
+    }
+
+    /// Releases the write lock without a guard, as in
+    /// [force_unlock_read](Self::force_unlock_read).
+    ///
+    /// # Safety
+    ///
+    /// The current context must logically own the write lock through a
+    /// [PinnedRwLockWriteGuard] that was discarded with [mem::forget].
+    pub unsafe fn force_unlock_write(self: Pin<&Self>) {
+        // SAFETY: the caller owns the write lock.
+        unsafe { self.get_ref().inner.force_unlock_write() }
+    }
+}
+
+impl<T: ?Sized + 'static> PinnedRwLock<T> {
+    /// Like [read](Self::read), but the guard holds a reference count
+    /// instead of a borrow, so it is `'static` and can be stashed in
+    /// futures or callbacks.
+    pub fn read_arc(self: Pin<Arc<Self>>) -> ArcPinnedRwLockReadGuard<T> {
+        // SAFETY: the guard holds the Arc until after the borrowed
+        // guard is dropped.
+        let lock = unsafe { crate::pin_arc_static(&self) };
+        ArcPinnedRwLockReadGuard {
+            guard: lock.read(),
+            _lock: self,
+        }
+    }
+
+    /// Like [write](Self::write), but returns an owned guard as in
+    /// [read_arc](Self::read_arc).
+    pub fn write_arc(self: Pin<Arc<Self>>) -> ArcPinnedRwLockWriteGuard<T> {
+        // SAFETY: as in read_arc.
+        let lock = unsafe { crate::pin_arc_static(&self) };
+        ArcPinnedRwLockWriteGuard {
+            guard: lock.write(),
+            _lock: self,
+        }
+    }
+
+    /// Like [upgradable_read](Self::upgradable_read), but returns an
+    /// owned guard as in [read_arc](Self::read_arc).
+    pub fn upgradable_read_arc(self: Pin<Arc<Self>>) -> ArcPinnedRwLockUpgradableReadGuard<T> {
+        // SAFETY: as in read_arc.
+        let lock = unsafe { crate::pin_arc_static(&self) };
+        ArcPinnedRwLockUpgradableReadGuard {
+            guard: lock.upgradable_read(),
+            _lock: self,
+        }
+    }
+}
+
+impl<T> From<T> for PinnedRwLock<T> {
+    fn from(init: T) -> Self {
+        Self::new(init)
+    }
+}
+
+/// Provides shared access to a [PinnedRwLock]'s contents. [Deref] to
+/// `&T` is always possible.
+///
+/// `as_ref` projects structural pinning.
+pub struct PinnedRwLockReadGuard<'a, T: ?Sized + 'a> {
+    guard: RwLockReadGuard<'a, T>,
+}
+
+impl<'a, T: ?Sized> PinnedRwLockReadGuard<'a, T> {
+    /// Provides pinned access to the underlying T.
+    pub fn as_ref(&self) -> Pin<&T> {
+        // PinnedRwLock::read requires the lock is pinned.
+        unsafe { Pin::new_unchecked(&self.guard) }
+    }
+
+    /// Narrows the guard to the part of the contents selected by `f`,
+    /// keeping the read lock held.
+    pub fn map<U: ?Sized>(
+        self,
+        f: impl FnOnce(Pin<&T>) -> Pin<&U>,
+    ) -> MappedPinnedRwLockReadGuard<'a, U> {
+        let guard = RwLockReadGuard::map(self.guard, |data| {
+            // SAFETY: PinnedRwLock::read requires the lock is pinned.
+            f(unsafe { Pin::new_unchecked(data) }).get_ref()
+        });
+        MappedPinnedRwLockReadGuard { guard }
+    }
+
+    /// Like [map](Self::map), but `f` may decline, in which case the
+    /// original guard is returned.
+    pub fn try_map<U: ?Sized>(
+        self,
+        f: impl FnOnce(Pin<&T>) -> Option<Pin<&U>>,
+    ) -> Result<MappedPinnedRwLockReadGuard<'a, U>, Self> {
+        let mapped = RwLockReadGuard::try_map(self.guard, |data| {
+            // SAFETY: as in map.
+            f(unsafe { Pin::new_unchecked(data) }).map(Pin::get_ref)
+        });
+        match mapped {
+            Ok(guard) => Ok(MappedPinnedRwLockReadGuard { guard }),
+            Err(guard) => Err(Self { guard }),
+        }
+    }
+
+    /// Releases the read lock fairly, handing it directly to a waiting
+    /// thread if there is one.
+    pub fn unlock_fair(self) {
+        RwLockReadGuard::unlock_fair(self.guard)
+    }
+
+    /// Temporarily releases the read lock to run `f`, and reacquires it
+    /// before returning. The contents are pinned, so they stay put
+    /// while other threads hold the lock.
+    pub fn unlocked<U>(&mut self, f: impl FnOnce() -> U) -> U {
+        RwLockReadGuard::unlocked(&mut self.guard, f)
+    }
+
+    /// Like [unlocked](Self::unlocked), but releases the lock fairly,
+    /// as in [unlock_fair](Self::unlock_fair).
+    pub fn unlocked_fair<U>(&mut self, f: impl FnOnce() -> U) -> U {
+        RwLockReadGuard::unlocked_fair(&mut self.guard, f)
+    }
+
+    /// Hands the lock to a waiting thread, if there is one, and
+    /// reacquires it, so long read sections can let queued writers in between
+    /// steps.
+    pub fn bump(&mut self) {
+        RwLockReadGuard::bump(&mut self.guard)
+    }
+}
+
+impl<'a, T: ?Sized> Deref for PinnedRwLockReadGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Debug> fmt::Debug for PinnedRwLockReadGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Display> fmt::Display for PinnedRwLockReadGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+/// Provides exclusive access to a [PinnedRwLock]'s contents. [Deref]
+/// to `&T` is always possible. [DerefMut] to `&mut T` is only possible
+/// if T is `Unpin`.
+///
+/// `as_ref` and `as_mut` project structural pinning.
+pub struct PinnedRwLockWriteGuard<'a, T: ?Sized + 'a> {
+    guard: RwLockWriteGuard<'a, T>,
+}
+
+impl<'a, T: ?Sized> PinnedRwLockWriteGuard<'a, T> {
+    /// Provides pinned access to the underlying T.
+    pub fn as_ref(&self) -> Pin<&T> {
+        // PinnedRwLock::write requires the lock is pinned.
+        unsafe { Pin::new_unchecked(&self.guard) }
+    }
+
+    /// Provides pinned mutable access to the underlying T.
+    pub fn as_mut(&mut self) -> Pin<&mut T> {
+        // PinnedRwLock::write requires the lock is pinned.
+        // &mut self guarantees as_ref() cannot alias.
+        unsafe { Pin::new_unchecked(&mut self.guard) }
+    }
+
+    /// Narrows the guard to the part of the contents selected by `f`,
+    /// keeping the write lock held.
+    pub fn map<U: ?Sized>(
+        self,
+        f: impl FnOnce(Pin<&mut T>) -> Pin<&mut U>,
+    ) -> MappedPinnedRwLockWriteGuard<'a, U> {
+        let guard = RwLockWriteGuard::map(self.guard, |data| {
+            // SAFETY: PinnedRwLock::write requires the lock is pinned,
+            // and the mapped guard only hands the projection out
+            // pinned.
+            unsafe { f(Pin::new_unchecked(data)).get_unchecked_mut() }
+        });
+        MappedPinnedRwLockWriteGuard { guard }
+    }
+
+    /// Like [map](Self::map), but `f` may decline, in which case the
+    /// original guard is returned.
+    pub fn try_map<U: ?Sized>(
+        self,
+        f: impl FnOnce(Pin<&mut T>) -> Option<Pin<&mut U>>,
+    ) -> Result<MappedPinnedRwLockWriteGuard<'a, U>, Self> {
+        let mapped = RwLockWriteGuard::try_map(self.guard, |data| {
+            // SAFETY: as in map.
+            unsafe { f(Pin::new_unchecked(data)).map(|u| u.get_unchecked_mut()) }
+        });
+        match mapped {
+            Ok(guard) => Ok(MappedPinnedRwLockWriteGuard { guard }),
+            Err(guard) => Err(Self { guard }),
+        }
+    }
+
+    /// Releases the write lock fairly, handing it directly to a waiting
+    /// thread if there is one.
+    pub fn unlock_fair(self) {
+        RwLockWriteGuard::unlock_fair(self.guard)
+    }
+
+    /// Temporarily releases the write lock to run `f`, and reacquires it
+    /// before returning. The contents are pinned, so they stay put
+    /// while other threads hold the lock.
+    pub fn unlocked<U>(&mut self, f: impl FnOnce() -> U) -> U {
+        RwLockWriteGuard::unlocked(&mut self.guard, f)
+    }
+
+    /// Like [unlocked](Self::unlocked), but releases the lock fairly,
+    /// as in [unlock_fair](Self::unlock_fair).
+    pub fn unlocked_fair<U>(&mut self, f: impl FnOnce() -> U) -> U {
+        RwLockWriteGuard::unlocked_fair(&mut self.guard, f)
+    }
+
+    /// Hands the lock to a waiting thread, if there is one, and
+    /// reacquires it, so long write sections can let queued writers in between
+    /// steps.
+    pub fn bump(&mut self) {
+        RwLockWriteGuard::bump(&mut self.guard)
+    }
+
+    /// Atomically turns the write lock into a read lock, letting other
+    /// readers in without any writer getting in between.
+    pub fn downgrade(self) -> PinnedRwLockReadGuard<'a, T> {
+        PinnedRwLockReadGuard {
+            guard: RwLockWriteGuard::downgrade(self.guard),
+        }
+    }
+}
+
+impl<'a, T: ?Sized> Deref for PinnedRwLockWriteGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl<'a, T: ?Sized + Unpin> DerefMut for PinnedRwLockWriteGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.guard
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Debug> fmt::Debug for PinnedRwLockWriteGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Display> fmt::Display for PinnedRwLockWriteGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+/// A [PinnedRwLockReadGuard] narrowed to part of the contents by
+/// [map](PinnedRwLockReadGuard::map). [Deref] to `&U` is always
+/// possible.
+///
+/// `as_ref` projects structural pinning.
+pub struct MappedPinnedRwLockReadGuard<'a, U: ?Sized + 'a> {
+    guard: MappedRwLockReadGuard<'a, U>,
+}
+
+impl<'a, U: ?Sized> MappedPinnedRwLockReadGuard<'a, U> {
+    /// Provides pinned access to the projected U.
+    pub fn as_ref(&self) -> Pin<&U> {
+        // SAFETY: map pinned the projection.
+        unsafe { Pin::new_unchecked(&self.guard) }
+    }
+}
+
+impl<'a, U: ?Sized> Deref for MappedPinnedRwLockReadGuard<'a, U> {
+    type Target = U;
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl<'a, U: ?Sized + fmt::Debug> fmt::Debug for MappedPinnedRwLockReadGuard<'a, U> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<'a, U: ?Sized + fmt::Display> fmt::Display for MappedPinnedRwLockReadGuard<'a, U> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+/// A [PinnedRwLockWriteGuard] narrowed to part of the contents by
+/// [map](PinnedRwLockWriteGuard::map). [Deref] to `&U` is always
+/// possible. [DerefMut] to `&mut U` is only possible if U is `Unpin`.
+///
+/// `as_ref` and `as_mut` project structural pinning.
+pub struct MappedPinnedRwLockWriteGuard<'a, U: ?Sized + 'a> {
+    guard: MappedRwLockWriteGuard<'a, U>,
+}
+
+impl<'a, U: ?Sized> MappedPinnedRwLockWriteGuard<'a, U> {
+    /// Provides pinned access to the projected U.
+    pub fn as_ref(&self) -> Pin<&U> {
+        // SAFETY: map pinned the projection.
+        unsafe { Pin::new_unchecked(&self.guard) }
+    }
+
+    /// Provides pinned mutable access to the projected U.
+    pub fn as_mut(&mut self) -> Pin<&mut U> {
+        // SAFETY: as in as_ref. &mut self guarantees as_ref() cannot
+        // alias.
+        unsafe { Pin::new_unchecked(&mut self.guard) }
+    }
+}
+
+impl<'a, U: ?Sized> Deref for MappedPinnedRwLockWriteGuard<'a, U> {
+    type Target = U;
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl<'a, U: ?Sized + Unpin> DerefMut for MappedPinnedRwLockWriteGuard<'a, U> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.guard
+    }
+}
+
+impl<'a, U: ?Sized + fmt::Debug> fmt::Debug for MappedPinnedRwLockWriteGuard<'a, U> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<'a, U: ?Sized + fmt::Display> fmt::Display for MappedPinnedRwLockWriteGuard<'a, U> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+/// Provides shared access to a [PinnedRwLock]'s contents that can be
+/// upgraded to exclusive access. [Deref] to `&T` is always possible.
+///
+/// `as_ref` projects structural pinning, and the contents stay pinned
+/// across upgrades and downgrades.
+pub struct PinnedRwLockUpgradableReadGuard<'a, T: ?Sized + 'a> {
+    guard: RwLockUpgradableReadGuard<'a, T>,
+}
+
+impl<'a, T: ?Sized> PinnedRwLockUpgradableReadGuard<'a, T> {
+    /// Provides pinned access to the underlying T.
+    pub fn as_ref(&self) -> Pin<&T> {
+        // PinnedRwLock::upgradable_read requires the lock is pinned.
+        unsafe { Pin::new_unchecked(&self.guard) }
+    }
+
+    /// Waits for the other readers to leave and atomically upgrades
+    /// to a write lock.
+    pub fn upgrade(self) -> PinnedRwLockWriteGuard<'a, T> {
+        PinnedRwLockWriteGuard {
+            guard: RwLockUpgradableReadGuard::upgrade(self.guard),
+        }
+    }
+
+    /// Upgrades to a write lock if there are no other readers, and
+    /// otherwise returns the guard unchanged.
+    pub fn try_upgrade(self) -> Result<PinnedRwLockWriteGuard<'a, T>, Self> {
+        match RwLockUpgradableReadGuard::try_upgrade(self.guard) {
+            Ok(guard) => Ok(PinnedRwLockWriteGuard { guard }),
+            Err(guard) => Err(Self { guard }),
+        }
+    }
+
+    /// Atomically turns the upgradable lock into a plain read lock,
+    /// letting another upgradable reader in.
+    pub fn downgrade(self) -> PinnedRwLockReadGuard<'a, T> {
+        PinnedRwLockReadGuard {
+            guard: RwLockUpgradableReadGuard::downgrade(self.guard),
+        }
+    }
+}
+
+impl<'a, T: ?Sized> Deref for PinnedRwLockUpgradableReadGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Debug> fmt::Debug for PinnedRwLockUpgradableReadGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Display> fmt::Display for PinnedRwLockUpgradableReadGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+/// A [PinnedRwLockReadGuard] that keeps its lock alive, returned by
+/// [PinnedRwLock::read_arc].
+pub struct ArcPinnedRwLockReadGuard<T: ?Sized + 'static> {
+    /// Borrows from `_lock`, so it is declared first to drop first.
+    guard: PinnedRwLockReadGuard<'static, T>,
+    _lock: Pin<Arc<PinnedRwLock<T>>>,
+}
+
+impl<T: ?Sized> ArcPinnedRwLockReadGuard<T> {
+    /// Provides pinned access to the underlying T.
+    pub fn as_ref(&self) -> Pin<&T> {
+        self.guard.as_ref()
+    }
+}
+
+impl<T: ?Sized> Deref for ArcPinnedRwLockReadGuard<T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for ArcPinnedRwLockReadGuard<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: ?Sized + fmt::Display> fmt::Display for ArcPinnedRwLockReadGuard<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+/// A [PinnedRwLockWriteGuard] that keeps its lock alive, returned by
+/// [PinnedRwLock::write_arc].
+pub struct ArcPinnedRwLockWriteGuard<T: ?Sized + 'static> {
+    /// Borrows from `_lock`, so it is declared first to drop first.
+    guard: PinnedRwLockWriteGuard<'static, T>,
+    _lock: Pin<Arc<PinnedRwLock<T>>>,
+}
+
+impl<T: ?Sized> ArcPinnedRwLockWriteGuard<T> {
+    /// Provides pinned access to the underlying T.
+    pub fn as_ref(&self) -> Pin<&T> {
+        self.guard.as_ref()
+    }
+
+    /// Provides pinned mutable access to the underlying T.
+    pub fn as_mut(&mut self) -> Pin<&mut T> {
+        self.guard.as_mut()
+    }
+}
+
+impl<T: ?Sized> Deref for ArcPinnedRwLockWriteGuard<T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl<T: ?Sized + Unpin> DerefMut for ArcPinnedRwLockWriteGuard<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.guard
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for ArcPinnedRwLockWriteGuard<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: ?Sized + fmt::Display> fmt::Display for ArcPinnedRwLockWriteGuard<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+/// A [PinnedRwLockUpgradableReadGuard] that keeps its lock alive,
+/// returned by [PinnedRwLock::upgradable_read_arc].
+pub struct ArcPinnedRwLockUpgradableReadGuard<T: ?Sized + 'static> {
+    /// Borrows from `_lock`, so it is declared first to drop first.
+    guard: PinnedRwLockUpgradableReadGuard<'static, T>,
+    _lock: Pin<Arc<PinnedRwLock<T>>>,
+}
+
+impl<T: ?Sized> ArcPinnedRwLockUpgradableReadGuard<T> {
+    /// Provides pinned access to the underlying T.
+    pub fn as_ref(&self) -> Pin<&T> {
+        self.guard.as_ref()
+    }
+
+    /// Waits for the other readers to leave and atomically upgrades
+    /// to an owned write lock.
+    pub fn upgrade(self) -> ArcPinnedRwLockWriteGuard<T> {
+        ArcPinnedRwLockWriteGuard {
+            guard: self.guard.upgrade(),
+            _lock: self._lock,
+        }
+    }
+}
+
+impl<T: ?Sized> Deref for ArcPinnedRwLockUpgradableReadGuard<T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for ArcPinnedRwLockUpgradableReadGuard<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: ?Sized + fmt::Display> fmt::Display for ArcPinnedRwLockUpgradableReadGuard<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+/// Whether a timed [PinnedCondvar] wait returned because its timeout
+/// passed, as in [parking_lot::WaitTimeoutResult].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WaitTimeoutResult(bool);
+
+impl WaitTimeoutResult {
+    /// Returns true if the wait timed out rather than being notified.
+    pub fn timed_out(self) -> bool {
+        self.0
+    }
+}
+
+/// A condition variable for [PinnedMutex]. Built directly on
+/// `parking_lot_core`, keyed by its own address, so that waiters can
+/// be [requeued](Self::requeue_all) onto another condvar.
+///
+/// Like [parking_lot::Condvar], it may only be used with one mutex at
+/// a time, and [notify_all](Self::notify_all) wakes one waiter and
+/// lines the rest up behind it on the mutex, rather than waking them
+/// all to fight over the lock.
+///
+/// Waiters may sit on another condvar's queue after a requeue, so a
+/// condvar is `!Unpin`, and dropping one wakes anything still queued
+/// on it.
+#[derive(Debug, Default)]
+pub struct PinnedCondvar {
+    epoch: AtomicUsize,
+    /// The [relay key](PinnedMutex::relay_key) of the mutex the
+    /// current waiters use, or 0 if there are none.
+    mutex: AtomicUsize,
+    _pinned: PhantomPinned,
+}
+
+/// Tells a thread woken from a mutex's relay queue to wake the next
+/// one once it holds the lock.
+const RELAY_TOKEN: UnparkToken = UnparkToken(1);
+
+impl PinnedCondvar {
+    pub const fn new() -> PinnedCondvar {
+        PinnedCondvar {
+            epoch: AtomicUsize::new(0),
+            mutex: AtomicUsize::new(0),
+            _pinned: PhantomPinned,
+        }
+    }
+
+    fn key(&self) -> usize {
+        self as *const Self as usize
+    }
+
+    /// Releases `guard`'s lock, parks until notified or `timeout`, and
+    /// reacquires the lock. Returns whether the timeout passed.
+    fn park<'a, T>(
+        &self,
+        guard: PinnedMutexGuard<'a, T>,
+        timeout: Option<Instant>,
+    ) -> (PinnedMutexGuard<'a, T>, bool) {
+        let PinnedMutexGuard {
+            guard: inner,
+            mutex: pinned,
+            hold,
+        } = guard;
+        hold.finish();
+        let mutex = MutexGuard::mutex(&inner);
+        let relay = pinned.get_ref().relay_key();
+        let mut bad_mutex = false;
+        let mut timed_out_from = 0;
+        // SAFETY: before_sleep releases the lock only after this
+        // thread is queued, so no notification is lost, and the guard
+        // is forgotten below so it is released exactly once. If
+        // validation fails, the guard still owns the lock. Requeued
+        // waiters only land on a pinned condvar, which wakes them
+        // before it is dropped, so a key passed to timed_out that is
+        // neither ours nor the relay key is a live condvar. No
+        // callback panics or parks.
+        let result = unsafe {
+            parking_lot_core::park(
+                self.key(),
+                || match self.mutex.load(Ordering::Relaxed) {
+                    0 => {
+                        self.mutex.store(relay, Ordering::Relaxed);
+                        true
+                    }
+                    key if key == relay => true,
+                    _ => {
+                        bad_mutex = true;
+                        false
+                    }
+                },
+                || mutex.force_unlock(),
+                |key, was_last| {
+                    timed_out_from = key;
+                    if was_last && key != relay {
+                        let condvar = &*(key as *const PinnedCondvar);
+                        condvar.mutex.store(0, Ordering::Relaxed);
+                    }
+                },
+                DEFAULT_PARK_TOKEN,
+                timeout,
+            )
+        };
+        if bad_mutex {
+            // The guard unlocks during unwinding.
+            panic!("attempted to use a condition variable with more than one mutex");
+        }
+        mem::forget(inner);
+        let guard = pinned.lock();
+        if result == ParkResult::Unparked(RELAY_TOKEN) || timed_out_from == relay {
+            // SAFETY: the callback does not panic or park.
+            unsafe { parking_lot_core::unpark_one(relay, |_| RELAY_TOKEN) };
+        }
+        // A waiter moved onto the mutex was notified, even if its
+        // turn for the lock came after the deadline.
+        let timed_out = matches!(result, ParkResult::TimedOut) && timed_out_from != relay;
+        (guard, timed_out)
+    }
+
+    pub fn wait<'a, T>(&self, guard: PinnedMutexGuard<'a, T>) -> PinnedMutexGuard<'a, T> {
+        self.park(guard, None).0
+    }
+
+    pub fn wait_while<'a, T, F>(
+        &self,
+        mut guard: PinnedMutexGuard<'a, T>,
+        mut condition: F,
+    ) -> PinnedMutexGuard<'a, T>
+    where
+        F: FnMut(Pin<&mut T>) -> bool,
+    {
+        while condition(guard.as_mut()) {
+            guard = self.wait(guard);
+        }
+        guard
+    }
+
+    /// Like [wait](Self::wait), but gives up at `deadline`. Returns the
+    /// guard and whether the deadline passed.
+    pub fn wait_deadline<'a, T>(
+        &self,
+        guard: PinnedMutexGuard<'a, T>,
+        deadline: impl Into<Deadline>,
+    ) -> (PinnedMutexGuard<'a, T>, bool) {
+        self.park(guard, deadline.into().to_instant())
+    }
+
+    /// Like [wait](Self::wait), but gives up after `timeout`. Returns
+    /// the reacquired guard either way.
+    pub fn wait_for<'a, T>(
+        &self,
+        guard: PinnedMutexGuard<'a, T>,
+        timeout: Duration,
+    ) -> (PinnedMutexGuard<'a, T>, WaitTimeoutResult) {
+        let (guard, timed_out) = self.wait_deadline(guard, timeout);
+        (guard, WaitTimeoutResult(timed_out))
+    }
+
+    /// Like [wait](Self::wait), but gives up at `deadline`. Returns
+    /// the reacquired guard either way.
+    pub fn wait_until<'a, T>(
+        &self,
+        guard: PinnedMutexGuard<'a, T>,
+        deadline: Instant,
+    ) -> (PinnedMutexGuard<'a, T>, WaitTimeoutResult) {
+        let (guard, timed_out) = self.wait_deadline(guard, deadline);
+        (guard, WaitTimeoutResult(timed_out))
+    }
+
+    /// Like [wait_while](Self::wait_while), but gives up at
+    /// `deadline`. Returns the guard and whether the deadline passed
+    /// with `condition` still true.
+    pub fn wait_while_deadline<'a, T, F>(
+        &self,
+        mut guard: PinnedMutexGuard<'a, T>,
+        deadline: impl Into<Deadline>,
+        mut condition: F,
+    ) -> (PinnedMutexGuard<'a, T>, bool)
+    where
+        F: FnMut(Pin<&mut T>) -> bool,
+    {
+        let deadline = Deadline::from(deadline.into().to_instant());
+        while condition(guard.as_mut()) {
+            let (next, timed_out) = self.wait_deadline(guard, deadline);
+            guard = next;
+            if timed_out {
+                let still = condition(guard.as_mut());
+                return (guard, still);
+            }
+        }
+        (guard, false)
+    }
+
+    /// Returns the number of notifications sent so far, wrapping on
+    /// overflow. Record it before releasing the lock, and pass it to
+    /// [wait_for_epoch](Self::wait_for_epoch) to wait for a
+    /// notification sent after that point.
+    pub fn epoch(&self) -> usize {
+        self.epoch.load(Ordering::SeqCst)
+    }
+
+    /// Blocks until a notification has been sent since
+    /// [epoch](Self::epoch) returned `epoch`, or returns immediately if
+    /// one already has.
+    ///
+    /// A notification is never missed if it is sent while holding the
+    /// mutex, the same contract as any condvar predicate.
+    pub fn wait_for_epoch<'a, T>(
+        &self,
+        guard: PinnedMutexGuard<'a, T>,
+        epoch: usize,
+    ) -> PinnedMutexGuard<'a, T> {
+        self.wait_while(guard, |_| self.epoch() == epoch)
+    }
+
+    /// Wakes one waiter. Returns whether a thread was woken.
+    pub fn notify_one(&self) -> bool {
+        self.epoch.fetch_add(1, Ordering::SeqCst);
+        // SAFETY: the callback does not panic or park.
+        let result = unsafe {
+            parking_lot_core::unpark_one(self.key(), |result| {
+                if !result.have_more_threads {
+                    self.mutex.store(0, Ordering::Relaxed);
+                }
+                DEFAULT_UNPARK_TOKEN
+            })
+        };
+        result.unparked_threads != 0
+    }
+
+    /// Wakes every waiter. Returns the number of threads woken.
+    ///
+    /// Only one thread is woken right away. The rest are moved to the
+    /// mutex, and each wakes the next once it holds the lock.
+    pub fn notify_all(&self) -> usize {
+        self.epoch.fetch_add(1, Ordering::SeqCst);
+        let relay = self.mutex.load(Ordering::Relaxed);
+        if relay == 0 {
+            return 0;
+        }
+        // SAFETY: neither callback panics or parks.
+        let result = unsafe {
+            parking_lot_core::unpark_requeue(
+                self.key(),
+                relay,
+                || {
+                    // Another mutex may have taken over after every
+                    // waiter left.
+                    if self.mutex.load(Ordering::Relaxed) != relay {
+                        return RequeueOp::Abort;
+                    }
+                    self.mutex.store(0, Ordering::Relaxed);
+                    RequeueOp::UnparkOneRequeueRest
+                },
+                |_, _| RELAY_TOKEN,
+            )
+        };
+        result.unparked_threads + result.requeued_threads
+    }
+
+    /// Moves one waiter, without waking it, to wait on `target`
+    /// instead. It wakes when `target` is notified, reacquires the
+    /// same mutex, and returns from its wait as usual. Returns whether
+    /// a waiter was moved.
+    ///
+    /// `target` is pinned so that it cannot move out from under the
+    /// waiters, and it wakes them if it is dropped first.
+    ///
+    /// Panics if `target` has waiters using a different mutex.
+    pub fn requeue_one(&self, target: Pin<&PinnedCondvar>) -> bool {
+        self.requeue(target, RequeueOp::RequeueOne) != 0
+    }
+
+    /// Moves every waiter, without waking them, to wait on `target`
+    /// instead, as in [requeue_one](Self::requeue_one). Returns the
+    /// number of waiters moved.
+    pub fn requeue_all(&self, target: Pin<&PinnedCondvar>) -> usize {
+        self.requeue(target, RequeueOp::RequeueAll)
+    }
+
+    fn requeue(&self, target: Pin<&PinnedCondvar>, op: RequeueOp) -> usize {
+        let target = target.get_ref();
+        if self.key() == target.key() {
+            return 0;
+        }
+        let mut bad_mutex = false;
+        // SAFETY: neither callback panics or parks.
+        let result = unsafe {
+            parking_lot_core::unpark_requeue(
+                self.key(),
+                target.key(),
+                || {
+                    let relay = self.mutex.load(Ordering::Relaxed);
+                    if relay == 0 {
+                        return RequeueOp::Abort;
+                    }
+                    match target.mutex.load(Ordering::Relaxed) {
+                        0 => target.mutex.store(relay, Ordering::Relaxed),
+                        key if key == relay => {}
+                        _ => {
+                            bad_mutex = true;
+                            return RequeueOp::Abort;
+                        }
+                    }
+                    op
+                },
+                |_, result| {
+                    if !result.have_more_threads {
+                        self.mutex.store(0, Ordering::Relaxed);
+                    }
+                    DEFAULT_UNPARK_TOKEN
+                },
+            )
+        };
+        if bad_mutex {
+            panic!("attempted to requeue onto a condition variable used with another mutex");
+        }
+        result.requeued_threads
+    }
+}
+
+impl Drop for PinnedCondvar {
+    fn drop(&mut self) {
+        // Only requeued waiters can remain, since a thread waiting
+        // here directly borrows the condvar. Wake them rather than
+        // leave them parked on a dead address.
+        // SAFETY: unpark_all takes no callbacks.
+        unsafe { parking_lot_core::unpark_all(self.key(), DEFAULT_UNPARK_TOKEN) };
+    }
+}
+
+/// A [PinnedMutex] paired with the [PinnedCondvar] that signals
+/// changes to its contents.
+///
+/// [lock_notify](Self::lock_notify) returns a [NotifyingGuard], which
+/// notifies the condvar itself when it is dropped after a mutation,
+/// so an update can't forget to wake its waiters.
+#[derive(Debug, Default)]
+pub struct PinnedMonitor<T> {
+    mutex: PinnedMutex<T>,
+    condvar: PinnedCondvar,
 }
 
-impl<T> PinnedMutex<T> {
+impl<T> PinnedMonitor<T> {
     pub fn new(init: T) -> Self {
         Self {
-            inner: Mutex::new(init),
+            mutex: PinnedMutex::new(init),
+            condvar: PinnedCondvar::new(),
         }
     }
 
-    /// Acquires the lock and returns a guard.
-    ///
-    /// [parking_lot] does not support poisoning. Neither does this.
+    /// Returns the pinned mutex.
+    pub fn mutex(self: Pin<&Self>) -> Pin<&PinnedMutex<T>> {
+        // SAFETY: mutex is structurally pinned.
+        unsafe { self.map_unchecked(|m| &m.mutex) }
+    }
+
+    /// Returns the condvar.
+    pub fn condvar(&self) -> &PinnedCondvar {
+        &self.condvar
+    }
+
+    /// Acquires the lock without arranging any notification.
     pub fn lock(self: Pin<&Self>) -> PinnedMutexGuard<'_, T> {
-        let guard = self.get_ref().inner.lock();
-        PinnedMutexGuard { guard }
+        self.mutex().lock()
+    }
+
+    /// Acquires the lock and returns a guard that notifies the condvar
+    /// according to `policy` when dropped, if the contents were
+    /// mutably accessed through it.
+    pub fn lock_notify(self: Pin<&Self>, policy: NotifyPolicy) -> NotifyingGuard<'_, T> {
+        NotifyingGuard {
+            guard: ManuallyDrop::new(self.lock()),
+            condvar: &self.get_ref().condvar,
+            policy,
+            mutated: false,
+        }
+    }
+
+    /// Acquires the lock, giving up after `timeout`.
+    pub fn lock_timeout(self: Pin<&Self>, timeout: Duration) -> Option<PinnedMutexGuard<'_, T>> {
+        self.mutex().try_lock_deadline(timeout)
+    }
+
+    /// Acquires the lock within `timeout`, passes the contents to `f`,
+    /// and notifies the condvar according to `policy` after releasing
+    /// the lock. Returns `None`, without calling `f`, if the lock was
+    /// not acquired in time.
+    pub fn update_and_notify_within<R>(
+        self: Pin<&Self>,
+        timeout: Duration,
+        policy: NotifyPolicy,
+        f: impl FnOnce(Pin<&mut T>) -> R,
+    ) -> Option<R> {
+        let mut guard = NotifyingGuard {
+            guard: ManuallyDrop::new(self.lock_timeout(timeout)?),
+            condvar: &self.get_ref().condvar,
+            policy,
+            mutated: false,
+        };
+        Some(f(guard.as_mut()))
+    }
+
+    pub fn wait<'a>(&self, guard: PinnedMutexGuard<'a, T>) -> PinnedMutexGuard<'a, T> {
+        self.condvar.wait(guard)
+    }
+
+    pub fn wait_while<'a, F>(
+        &self,
+        guard: PinnedMutexGuard<'a, T>,
+        condition: F,
+    ) -> PinnedMutexGuard<'a, T>
+    where
+        F: FnMut(Pin<&mut T>) -> bool,
+    {
+        self.condvar.wait_while(guard, condition)
+    }
+
+    /// Like [wait_while](Self::wait_while), but gives up after
+    /// `timeout`. Returns the guard and whether it timed out with
+    /// `condition` still true.
+    pub fn wait_while_for<'a, F>(
+        &self,
+        guard: PinnedMutexGuard<'a, T>,
+        timeout: Duration,
+        condition: F,
+    ) -> (PinnedMutexGuard<'a, T>, bool)
+    where
+        F: FnMut(Pin<&mut T>) -> bool,
+    {
+        self.condvar.wait_while_deadline(guard, timeout, condition)
+    }
+
+    /// Wakes one waiter. Returns whether a thread was woken.
+    pub fn notify_one(&self) -> bool {
+        self.condvar.notify_one()
+    }
+
+    /// Wakes every waiter. Returns the number of threads woken.
+    pub fn notify_all(&self) -> usize {
+        self.condvar.notify_all()
     }
 }
 
-/// Provides access to mutex's contents. [Deref] to `&T` is always
-/// possible. [DerefMut] to `&mut T` is only possive if T is `Unpin`.
-///
-/// `as_ref` and `as_mut` project structural pinning.
-pub struct PinnedMutexGuard<'a, T: 'a> {
-    guard: MutexGuard<'a, T>,
+/// A [PinnedMutexGuard] returned by [PinnedMonitor::lock_notify].
+/// Dropping it releases the lock and then, if `as_mut` or [DerefMut]
+/// was used, notifies the monitor's condvar.
+pub struct NotifyingGuard<'a, T: 'a> {
+    guard: ManuallyDrop<PinnedMutexGuard<'a, T>>,
+    condvar: &'a PinnedCondvar,
+    policy: NotifyPolicy,
+    mutated: bool,
 }
 
-impl<'a, T> PinnedMutexGuard<'a, T> {
+impl<'a, T> NotifyingGuard<'a, T> {
     /// Provides pinned access to the underlying T.
     pub fn as_ref(&self) -> Pin<&T> {
-        // PinnedMutex::lock requires the mutex is pinned.
-        unsafe { Pin::new_unchecked(&self.guard) }
+        self.guard.as_ref()
     }
 
-    /// Provides pinned mutable access to the underlying T.
+    /// Provides pinned mutable access to the underlying T, and
+    /// arranges for a notification on drop.
     pub fn as_mut(&mut self) -> Pin<&mut T> {
-        // PinnedMutex::lock requires the mutex is pinned.
-        // &mut self guarantees as_ref() cannot alias.
-        unsafe { Pin::new_unchecked(&mut self.guard) }
+        self.mutated = true;
+        self.guard.as_mut()
+    }
+
+    /// Returns whether the guard will notify when dropped.
+    pub fn is_mutated(&self) -> bool {
+        self.mutated
     }
 }
 
-impl<'a, T> Deref for PinnedMutexGuard<'a, T> {
+impl<'a, T> Deref for NotifyingGuard<'a, T> {
     type Target = T;
     fn deref(&self) -> &Self::Target {
         &self.guard
     }
 }
 
-impl<'a, T: Unpin> DerefMut for PinnedMutexGuard<'a, T> {
+impl<'a, T: Unpin> DerefMut for NotifyingGuard<'a, T> {
     fn deref_mut(&mut self) -> &mut Self::Target {
+        self.mutated = true;
         &mut self.guard
     }
 }
 
-#[derive(Debug, Default)]
-pub struct PinnedCondvar(Condvar);
-
-impl PinnedCondvar {
-    pub fn new() -> PinnedCondvar {
-        Default::default()
+impl<'a, T> Drop for NotifyingGuard<'a, T> {
+    fn drop(&mut self) {
+        // SAFETY: the guard is never used again. Unlocking first lets
+        // woken waiters take the lock immediately.
+        unsafe { ManuallyDrop::drop(&mut self.guard) };
+        if self.mutated {
+            match self.policy {
+                NotifyPolicy::One => {
+                    self.condvar.notify_one();
+                }
+                NotifyPolicy::All => {
+                    self.condvar.notify_all();
+                }
+            }
+        }
     }
+}
 
-    pub fn wait<'a, T>(&self, guard: PinnedMutexGuard<'a, T>) -> PinnedMutexGuard<'a, T> {
-        let mut inner = guard.guard;
-        self.0.wait(&mut inner);
-        PinnedMutexGuard { guard: inner }
-    }
+/// One thread in a deadlock cycle reported by [check_deadlock].
+#[cfg_attr(docsrs, doc(cfg(feature = "deadlock_detection")))]
+#[cfg(feature = "deadlock_detection")]
+#[derive(Clone, Debug)]
+pub struct DeadlockedThread {
+    thread_id: usize,
+    backtrace: String,
+}
 
-    pub fn wait_while<'a, T, F>(
-        &self,
-        guard: PinnedMutexGuard<'a, T>,
-        mut condition: F,
-    ) -> PinnedMutexGuard<'a, T>
-    where
-        F: FnMut(Pin<&mut T>) -> bool,
-    {
-        let mut inner = guard.guard;
-        self.0.wait_while(&mut inner, move |v| {
-            // SAFETY: v is never moved.
-            condition(unsafe { Pin::new_unchecked(v) })
-        });
-        PinnedMutexGuard { guard: inner }
+#[cfg(feature = "deadlock_detection")]
+impl DeadlockedThread {
+    /// Returns the system thread ID.
+    pub fn thread_id(&self) -> usize {
+        self.thread_id
     }
 
-    pub fn notify_one(&self) {
-        self.0.notify_one();
+    /// Returns the thread's backtrace at the time it blocked,
+    /// formatted for logging.
+    pub fn backtrace(&self) -> &str {
+        &self.backtrace
     }
+}
 
-    pub fn notify_all(&self) {
-        self.0.notify_all();
-    }
+/// Returns each cycle of threads deadlocked on parking_lot locks,
+/// including the pinned wrappers in this module, that was detected
+/// since the last call. Call it periodically from a watchdog thread.
+#[cfg_attr(docsrs, doc(cfg(feature = "deadlock_detection")))]
+#[cfg(feature = "deadlock_detection")]
+pub fn check_deadlock() -> Vec<Vec<DeadlockedThread>> {
+    parking_lot::deadlock::check_deadlock()
+        .into_iter()
+        .map(|cycle| {
+            cycle
+                .into_iter()
+                .map(|thread| DeadlockedThread {
+                    thread_id: thread.thread_id(),
+                    backtrace: format!("{:?}", thread.backtrace()),
+                })
+                .collect()
+        })
+        .collect()
 }
 
 #[cfg(test)]
@@ -170,8 +2323,8 @@ mod tests {
         cv.wait_while(locked, |pinned_contents| {
             pinned_contents.as_ref().get() == 0
         });
-        cv.notify_one();
-        cv.notify_all();
+        assert!(!cv.notify_one());
+        assert_eq!(0, cv.notify_all());
     }
 
     #[derive(Debug, Default)]
@@ -182,4 +2335,673 @@ mod tests {
         let pm: PinnedMutex<DebugTest> = Default::default();
         _ = format!("{:?}", pm);
     }
+
+    #[pin_project]
+    struct TwoParts {
+        #[pin]
+        left: MustPin,
+        right: u32,
+    }
+
+    #[test]
+    fn view_projects_field() {
+        let pm = pin!(PinnedMutex::new(TwoParts {
+            left: MustPin::new(),
+            right: 10,
+        }));
+        let left = pm.as_ref().view(|parts| parts.project().left);
+        let right = pm.as_ref().view(|parts| Pin::new(parts.project().right));
+        assert_eq!(0, left.lock().as_mut().inc());
+        *right.lock() += 1;
+        let locked = pm.as_ref().lock();
+        assert_eq!(1, locked.left.value);
+        assert_eq!(11, locked.right);
+    }
+
+    #[test]
+    fn swap_contents() {
+        let a = pin!(PinnedMutex::new(vec![1]));
+        let b = pin!(PinnedMutex::new(vec![2, 3]));
+        a.as_ref().swap(b.as_ref());
+        assert_eq!(vec![2, 3], *a.as_ref().lock());
+        a.as_ref().swap(a.as_ref());
+        let held = b.as_ref().lock();
+        assert!(!a.as_ref().try_swap(b.as_ref()));
+        drop(held);
+        assert!(a.as_ref().try_swap(b.as_ref()));
+        assert_eq!(vec![1], *a.as_ref().lock());
+    }
+
+    #[test]
+    fn scope_workers() {
+        let pm = pin!(PinnedMutex::new(MustPin::new()));
+        pm.as_ref().scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|scope| scope.with_lock(|n| n.inc()));
+            }
+        });
+        assert_eq!(4, pm.as_ref().lock().as_ref().get());
+    }
+
+    #[test]
+    fn wait_deadline_times_out() {
+        let cv = PinnedCondvar::new();
+        let pm = pin!(PinnedMutex::new(0));
+        let timeout = std::time::Duration::from_millis(10);
+        let (guard, timed_out) = cv.wait_deadline(pm.as_ref().lock(), timeout);
+        assert!(timed_out);
+        let (guard, timed_out) = cv.wait_while_deadline(guard, timeout, |n| *n == 0);
+        assert!(timed_out);
+        let (_guard, timed_out) = cv.wait_while_deadline(guard, Deadline::Never, |n| *n != 0);
+        assert!(!timed_out);
+    }
+
+    #[test]
+    fn wait_for_and_until() {
+        let cv = PinnedCondvar::new();
+        let pm = pin!(PinnedMutex::new(0));
+        let pm = pm.as_ref();
+        let (guard, result) = cv.wait_for(pm.lock(), Duration::from_millis(10));
+        assert!(result.timed_out());
+        let (mut guard, result) = cv.wait_until(guard, Instant::now());
+        assert!(result.timed_out());
+        thread::scope(|s| {
+            s.spawn(|| {
+                *pm.lock() = 1;
+                cv.notify_one();
+            });
+            while *guard == 0 {
+                let (next, result) = cv.wait_for(guard, Duration::from_secs(60));
+                assert!(!result.timed_out());
+                guard = next;
+            }
+        });
+    }
+
+    #[cfg(feature = "deadlock_detection")]
+    #[test]
+    fn no_deadlock_reported() {
+        let pm = pin!(PinnedMutex::new(0));
+        let _guard = pm.as_ref().lock();
+        assert!(check_deadlock().is_empty());
+    }
+
+    #[cfg(feature = "send_guard")]
+    #[test]
+    fn guard_released_on_another_thread() {
+        let pm = pin!(PinnedMutex::new(MustPin::new()));
+        let pm = pm.as_ref();
+        let mut guard = pm.lock();
+        guard.as_mut().inc();
+        thread::scope(|s| {
+            s.spawn(move || drop(guard));
+        });
+        assert_eq!(1, pm.try_lock().unwrap().as_ref().get());
+    }
+
+    #[test]
+    fn pinned_static() {
+        static COUNTER: PinnedMutex<u32> = PinnedMutex::new(0);
+        static CHANGED: PinnedCondvar = PinnedCondvar::new();
+        let counter = COUNTER.pin_static();
+        thread::spawn(move || {
+            *counter.lock() += 1;
+            CHANGED.notify_all();
+        });
+        let guard = CHANGED.wait_while(counter.lock(), |n| *n == 0);
+        assert_eq!(1, *guard);
+    }
+
+    #[test]
+    fn contention_hint() {
+        let pm = pin!(PinnedMutex::new(0));
+        let pm = pm.as_ref();
+        assert!(!pm.is_contended());
+        let guard = pm.lock();
+        std::thread::scope(|s| {
+            s.spawn(|| *pm.lock() += 1);
+            while !pm.is_contended() {
+                std::thread::yield_now();
+            }
+            assert_eq!(1, pm.waiters());
+            drop(guard);
+        });
+        assert!(!pm.is_contended());
+        assert_eq!(1, *pm.lock());
+    }
+
+    #[test]
+    fn monitor_notifies_on_mutation() {
+        let monitor = pin!(PinnedMonitor::new(0));
+        let monitor = monitor.as_ref();
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                let guard = monitor.wait_while(monitor.lock(), |n| *n == 0);
+                assert_eq!(1, *guard);
+            });
+            let reader = monitor.lock_notify(NotifyPolicy::One);
+            assert!(!reader.is_mutated());
+            drop(reader);
+            *monitor.lock_notify(NotifyPolicy::All) += 1;
+        });
+    }
+
+    #[test]
+    fn notify_reports_woken_threads() {
+        let cv = PinnedCondvar::new();
+        let pm = pin!(PinnedMutex::new((0, false)));
+        let pm = pm.as_ref();
+        thread::scope(|s| {
+            for _ in 0..2 {
+                s.spawn(|| {
+                    let mut guard = pm.lock();
+                    guard.0 += 1;
+                    drop(cv.wait_while(guard, |state| !state.1));
+                });
+            }
+            // Waiters count themselves under the lock and release it
+            // only once parked, so both are parked once both counted.
+            loop {
+                let mut guard = pm.lock();
+                if guard.0 == 2 {
+                    guard.1 = true;
+                    break;
+                }
+                drop(guard);
+                thread::yield_now();
+            }
+            assert_eq!(2, cv.notify_all());
+        });
+        assert!(!cv.notify_one());
+    }
+
+    #[test]
+    fn epoch_catches_earlier_notify() {
+        let cv = PinnedCondvar::new();
+        let pm = pin!(PinnedMutex::new(0));
+        let epoch = cv.epoch();
+        cv.notify_one();
+        // Does not block.
+        let guard = cv.wait_for_epoch(pm.as_ref().lock(), epoch);
+        assert_eq!(epoch.wrapping_add(1), cv.epoch());
+        drop(guard);
+    }
+
+    #[test]
+    fn usable_across_catch_unwind() {
+        let pm = pin!(PinnedMutex::new(MustPin::new()));
+        let pm = pm.as_ref();
+        let result = std::panic::catch_unwind(|| {
+            pm.lock().as_mut().inc();
+            panic!("after mutation");
+        });
+        assert!(result.is_err());
+        assert_eq!(1, pm.lock().as_ref().get());
+    }
+
+    #[test]
+    fn try_lock_while_held() {
+        let pm = pin!(PinnedMutex::new(MustPin::new()));
+        let mut locked = pm.as_ref().try_lock().unwrap();
+        assert_eq!(0, locked.as_mut().inc());
+        assert!(pm.as_ref().try_lock().is_none());
+        drop(locked);
+        assert_eq!(1, pm.as_ref().try_lock().unwrap().as_ref().get());
+    }
+
+    #[test]
+    fn timed_try_lock() {
+        let pm = pin!(PinnedMutex::new(0));
+        let pm = pm.as_ref();
+        let guard = pm.lock();
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                assert!(pm.try_lock_for(Duration::from_millis(5)).is_none());
+                let deadline = Instant::now() + Duration::from_millis(5);
+                assert!(pm.try_lock_until(deadline).is_none());
+            });
+        });
+        drop(guard);
+        *pm.try_lock_for(Duration::from_millis(5)).unwrap() += 1;
+        assert_eq!(1, *pm.try_lock_until(Instant::now()).unwrap());
+    }
+
+    #[test]
+    fn deadline_try_lock() {
+        let pm = pin!(PinnedMutex::new(0));
+        let pm = pm.as_ref();
+        let rw = pin!(PinnedRwLock::new(0));
+        let rw = rw.as_ref();
+        let guard = pm.lock();
+        let read = rw.read();
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                assert!(pm.try_lock_deadline(Duration::from_millis(5)).is_none());
+                assert!(rw.try_write_deadline(Duration::from_millis(5)).is_none());
+                assert!(rw.try_read_deadline(Deadline::Never).is_some());
+            });
+        });
+        drop(guard);
+        drop(read);
+        *pm.try_lock_deadline(Deadline::Never).unwrap() += 1;
+        assert_eq!(1, *pm.try_lock_deadline(Instant::now()).unwrap());
+        assert!(rw.try_write_deadline(Instant::now()).is_some());
+    }
+
+    #[test]
+    fn reports_locked() {
+        let pm = pin!(PinnedMutex::new(0));
+        let pm = pm.as_ref();
+        assert!(!pm.is_locked());
+        let guard = pm.lock();
+        assert!(pm.is_locked());
+        drop(guard);
+        assert!(!pm.is_locked());
+    }
+
+    #[test]
+    fn force_unlock_forgotten_guard() {
+        let pm = pin!(PinnedMutex::new(MustPin::new()));
+        let pm = pm.as_ref();
+        let mut guard = pm.lock();
+        guard.as_mut().inc();
+        mem::forget(guard);
+        // SAFETY: the forgotten guard owned the lock.
+        unsafe { pm.force_unlock() };
+        mem::forget(pm.lock());
+        // SAFETY: the lock is held, and the pointer is only used to
+        // call a pinned method.
+        unsafe { Pin::new_unchecked(&mut *pm.data_ptr()).inc() };
+        // SAFETY: the forgotten guard owned the lock.
+        unsafe { pm.force_unlock_fair() };
+        assert_eq!(2, pm.lock().as_ref().get());
+    }
+
+    #[test]
+    fn leaked_guard_stays_locked() {
+        let pm = pin!(PinnedMutex::new(MustPin::new()));
+        let pm = pm.as_ref();
+        let mut leaked = pm.lock().leak();
+        leaked.as_mut().inc();
+        assert!(pm.try_lock().is_none());
+        assert_eq!(1, leaked.as_ref().get());
+    }
+
+    #[test]
+    fn unlocked_releases_around_closure() {
+        let pm = pin!(PinnedMutex::new(MustPin::new()));
+        let pm = pm.as_ref();
+        let mut guard = pm.lock();
+        guard.as_mut().inc();
+        let observed = guard.unlocked(|| pm.lock().as_mut().inc());
+        assert_eq!(1, observed);
+        guard.unlocked_fair(|| assert!(!pm.is_locked()));
+        assert_eq!(2, guard.as_mut().inc());
+    }
+
+    #[test]
+    fn bump_lets_waiter_in() {
+        let pm = pin!(PinnedMutex::new(0));
+        let pm = pm.as_ref();
+        let mut guard = pm.lock();
+        guard.bump();
+        std::thread::scope(|s| {
+            s.spawn(|| *pm.lock() += 1);
+            while *guard == 0 {
+                guard.bump();
+            }
+        });
+        assert_eq!(1, *guard);
+    }
+
+    #[test]
+    fn mapped_guard_projects_field() {
+        let pm = pin!(PinnedMutex::new((MustPin::new(), 5)));
+        let pm = pm.as_ref();
+        let mut first = pm.lock().map(|pair| {
+            // SAFETY: the tuple's fields are structurally pinned.
+            unsafe { pair.map_unchecked_mut(|(first, _)| first) }
+        });
+        first.as_mut().inc();
+        assert!(pm.try_lock().is_none());
+        drop(first);
+        let declined = pm.lock().try_map(|_| None::<Pin<&mut u32>>);
+        let mut second = match declined {
+            Ok(_) => unreachable!(),
+            Err(guard) => guard.map(|pair| {
+                // SAFETY: as above.
+                unsafe { pair.map_unchecked_mut(|(_, second)| second) }
+            }),
+        };
+        *second += 1;
+        assert_eq!(6, *second);
+    }
+
+    #[test]
+    fn arc_guard_outlives_borrow() {
+        let pm = std::sync::Arc::pin(PinnedMutex::new(MustPin::new()));
+        let mut guard = pm.clone().lock_arc();
+        assert!(pm.clone().try_lock_arc().is_none());
+        drop(pm);
+        let mut bump = move || guard.as_mut().inc();
+        assert_eq!(0, bump());
+        assert_eq!(1, bump());
+    }
+
+    #[test]
+    fn arc_rwlock_guards_outlive_borrow() {
+        let rw = std::sync::Arc::pin(PinnedRwLock::new(MustPin::new()));
+        let upgradable = rw.clone().upgradable_read_arc();
+        let reader = rw.clone().read_arc();
+        assert_eq!(0, upgradable.as_ref().get());
+        drop(reader);
+        let mut writer = upgradable.upgrade();
+        let other = rw.clone();
+        drop(rw);
+        assert_eq!(0, writer.as_mut().inc());
+        drop(writer);
+        let reader = thread::spawn(move || other.read_arc().as_ref().get());
+        assert_eq!(1, reader.join().unwrap());
+    }
+
+    #[test]
+    fn fair_mutex_projects() {
+        let pm = pin!(PinnedFairMutex::new((MustPin::new(), 5)));
+        let pm = pm.as_ref();
+        let mut guard = pm.lock();
+        guard.unlocked(|| assert!(!pm.is_locked()));
+        guard.bump();
+        let mut first = guard.map(|pair| {
+            // SAFETY: the tuple's fields are structurally pinned.
+            unsafe { pair.map_unchecked_mut(|(first, _)| first) }
+        });
+        assert_eq!(0, first.as_mut().inc());
+        assert!(pm.try_lock().is_none());
+        drop(first);
+        let mut second = pm
+            .try_lock_for(Duration::from_millis(5))
+            .unwrap()
+            .map(|pair| {
+                // SAFETY: as above.
+                unsafe { pair.map_unchecked_mut(|(_, second)| second) }
+            });
+        *second += 1;
+        assert_eq!(6, *second);
+    }
+
+    #[test]
+    fn reentrant_mutex_reenters() {
+        let pm = pin!(PinnedReentrantMutex::new(std::cell::Cell::new(0)));
+        let pm = pm.as_ref();
+        let outer = pm.lock();
+        outer.set(1);
+        let inner = pm.try_lock().unwrap();
+        inner.set(inner.get() + 1);
+        assert!(pm.is_owned_by_current_thread());
+        drop(inner);
+        assert_eq!(2, outer.as_ref().get());
+        drop(outer);
+        assert!(!pm.is_locked());
+    }
+
+    #[test]
+    fn rwlock_readers_share() {
+        let rw = pin!(PinnedRwLock::new(MustPin::new()));
+        let rw = rw.as_ref();
+        rw.write().as_mut().inc();
+        let first = rw.read();
+        let second = rw.read();
+        assert_eq!(1, first.as_ref().get());
+        assert_eq!(1, second.as_ref().get());
+    }
+
+    #[test]
+    fn rwlock_upgrade_and_downgrade() {
+        let rw = pin!(PinnedRwLock::new(MustPin::new()));
+        let rw = rw.as_ref();
+        let reader = rw.read();
+        let upgradable = rw.upgradable_read();
+        assert_eq!(0, upgradable.as_ref().get());
+        let upgradable = match upgradable.try_upgrade() {
+            Ok(_) => panic!("upgraded past a reader"),
+            Err(upgradable) => upgradable,
+        };
+        drop(reader);
+        let mut writer = upgradable.upgrade();
+        writer.as_mut().inc();
+        let reader = writer.downgrade();
+        let other = rw.upgradable_read();
+        assert_eq!(1, reader.as_ref().get());
+        assert_eq!(1, other.downgrade().as_ref().get());
+    }
+
+    #[test]
+    fn rwlock_timed_acquire() {
+        let rw = pin!(PinnedRwLock::new(MustPin::new()));
+        let rw = rw.as_ref();
+        let reader = rw.read();
+        assert!(rw.try_write_for(Duration::from_millis(5)).is_none());
+        let deadline = Instant::now() + Duration::from_millis(5);
+        assert!(rw.try_write_until(deadline).is_none());
+        drop(reader);
+        let mut writer = rw.try_write_for(Duration::from_millis(5)).unwrap();
+        writer.as_mut().inc();
+        assert!(rw.try_read_for(Duration::from_millis(5)).is_none());
+        assert!(rw.try_read_until(Instant::now()).is_none());
+        drop(writer);
+        let reader = rw.try_read_for(Duration::from_millis(5)).unwrap();
+        assert_eq!(1, reader.as_ref().get());
+        assert_eq!(1, rw.try_read_until(Instant::now()).unwrap().as_ref().get());
+    }
+
+    #[test]
+    fn rwlock_recursive_read_passes_queued_writer() {
+        let rw = pin!(PinnedRwLock::new(MustPin::new()));
+        let rw = rw.as_ref();
+        let outer = rw.read();
+        thread::scope(|s| {
+            s.spawn(|| rw.write().as_mut().inc());
+            thread::sleep(Duration::from_millis(10));
+            assert_eq!(0, rw.read_recursive().as_ref().get());
+            assert_eq!(0, rw.try_read_recursive().unwrap().as_ref().get());
+            drop(outer);
+        });
+        let writer = rw.write();
+        assert!(rw.try_read_recursive().is_none());
+        assert_eq!(1, writer.as_ref().get());
+    }
+
+    #[test]
+    fn rwlock_force_unlock_forgotten_guards() {
+        let rw = pin!(PinnedRwLock::new(MustPin::new()));
+        let rw = rw.as_ref();
+        assert!(!rw.is_locked());
+        mem::forget(rw.write());
+        assert!(rw.is_locked_exclusive());
+        // SAFETY: the write lock is held, and the pointer is only used
+        // to call a pinned method.
+        unsafe { Pin::new_unchecked(&mut *rw.data_ptr()).inc() };
+        // SAFETY: the forgotten guard owned the write lock.
+        unsafe { rw.force_unlock_write() };
+        mem::forget(rw.read());
+        assert!(rw.is_locked());
+        assert!(!rw.is_locked_exclusive());
+        // SAFETY: the forgotten guard owned a read lock.
+        unsafe { rw.force_unlock_read() };
+        assert!(!rw.is_locked());
+        assert_eq!(1, rw.write().as_ref().get());
+    }
+
+    #[test]
+    fn rwlock_guards_release_temporarily() {
+        let rw = pin!(PinnedRwLock::new(MustPin::new()));
+        let rw = rw.as_ref();
+        let mut writer = rw.write();
+        writer.unlocked(|| rw.write().as_mut().inc());
+        writer.unlocked_fair(|| assert!(!rw.is_locked()));
+        writer.bump();
+        writer.as_mut().inc();
+        writer.unlock_fair();
+        let mut reader = rw.read();
+        reader.unlocked(|| assert!(rw.try_write_for(Duration::ZERO).is_some()));
+        reader.unlocked_fair(|| assert!(!rw.is_locked()));
+        reader.bump();
+        assert_eq!(2, reader.as_ref().get());
+        reader.unlock_fair();
+        assert!(!rw.is_locked());
+    }
+
+    #[test]
+    fn rwlock_mapped_guards() {
+        let rw = pin!(PinnedRwLock::new((MustPin::new(), 5)));
+        let rw = rw.as_ref();
+        let mut first = rw.write().map(|pair| {
+            // SAFETY: the tuple's fields are structurally pinned.
+            unsafe { pair.map_unchecked_mut(|(first, _)| first) }
+        });
+        first.as_mut().inc();
+        drop(first);
+        let second = rw.read().map(|pair| {
+            // SAFETY: as above.
+            unsafe { pair.map_unchecked(|(_, second)| second) }
+        });
+        assert_eq!(5, *second);
+        drop(second);
+        let declined = rw.write().try_map(|_| None::<Pin<&mut u32>>);
+        assert!(declined.is_err());
+        drop(declined);
+        let first = rw.read().try_map(|pair| {
+            // SAFETY: as above.
+            Some(unsafe { pair.map_unchecked(|(first, _)| first) })
+        });
+        assert_eq!(1, first.ok().unwrap().as_ref().get());
+    }
+
+    #[test]
+    fn guard_relocks_its_mutex() {
+        let pm = pin!(PinnedMutex::new(MustPin::new()));
+        let mut guard = pm.as_ref().lock();
+        guard.as_mut().inc();
+        let mutex = guard.mutex();
+        guard.unlock();
+        assert_eq!(1, mutex.lock().as_mut().inc());
+        mutex.lock().unlock_fair();
+        assert!(!mutex.is_locked());
+    }
+
+    #[test]
+    fn clone_copies_contents() {
+        let pm = pin!(PinnedMutex::new(vec![1, 2]));
+        let copy = pin!(pm.clone());
+        pm.as_ref().lock().push(3);
+        assert_eq!(vec![1, 2], *copy.as_ref().lock());
+        assert_eq!(vec![1, 2, 3], pm.as_ref().get_cloned());
+    }
+
+    #[test]
+    fn eq_by_contents() {
+        let a = PinnedMutex::new(1);
+        let b = PinnedMutex::new(1);
+        assert_eq!(a, b);
+        assert_eq!(a, a);
+        *Pin::new(&b).lock() = 2;
+        assert_ne!(b, a);
+    }
+
+    #[test]
+    fn guard_formats_contents() {
+        let pm = pin!(PinnedMutex::new(7));
+        let guard = pm.as_ref().lock();
+        assert_eq!("7", format!("{:?}", guard));
+        assert_eq!("7", format!("{}", guard));
+    }
+
+    #[test]
+    fn requeued_waiter_wakes_on_target() {
+        let a = PinnedCondvar::new();
+        let b = pin!(PinnedCondvar::new());
+        let b = b.as_ref();
+        let pm = pin!(PinnedMutex::new(0));
+        let pm = pm.as_ref();
+        let woke = std::sync::atomic::AtomicBool::new(false);
+        thread::scope(|s| {
+            s.spawn(|| {
+                drop(a.wait_while(pm.lock(), |v| *v == 0));
+                woke.store(true, Ordering::SeqCst);
+            });
+            while !a.requeue_one(b) {
+                thread::yield_now();
+            }
+            *pm.lock() = 1;
+            a.notify_all();
+            thread::sleep(std::time::Duration::from_millis(10));
+            assert!(!woke.load(Ordering::SeqCst));
+            b.notify_one();
+        });
+        assert!(woke.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn dropped_target_wakes_requeued_waiters() {
+        let a = PinnedCondvar::new();
+        let pm = pin!(PinnedMutex::new(0));
+        let pm = pm.as_ref();
+        thread::scope(|s| {
+            let waiter = s.spawn(|| drop(a.wait(pm.lock())));
+            let b = Box::pin(PinnedCondvar::new());
+            while !a.requeue_one(b.as_ref()) {
+                thread::yield_now();
+            }
+            drop(b);
+            waiter.join().unwrap();
+        });
+    }
+
+    #[test]
+    fn notify_all_wakes_every_waiter() {
+        let cv = PinnedCondvar::new();
+        let pm = pin!(PinnedMutex::new(0));
+        let pm = pm.as_ref();
+        let woken = AtomicUsize::new(0);
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    let mut guard = pm.lock();
+                    *guard += 1;
+                    drop(cv.wait_while(guard, |v| *v != 0));
+                    woken.fetch_add(1, Ordering::SeqCst);
+                });
+            }
+            let mut guard = pm.lock();
+            while *guard != 4 {
+                drop(guard);
+                thread::yield_now();
+                guard = pm.lock();
+            }
+            *guard = 0;
+            assert_eq!(4, cv.notify_all());
+        });
+        assert_eq!(4, woken.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn condvar_rejects_second_mutex() {
+        let cv = PinnedCondvar::new();
+        let first = pin!(PinnedMutex::new(0));
+        let first = first.as_ref();
+        let second = pin!(PinnedMutex::new(0));
+        let second = second.as_ref();
+        thread::scope(|s| {
+            s.spawn(|| drop(cv.wait_while(first.lock(), |v| *v == 0)));
+            while cv.mutex.load(Ordering::SeqCst) == 0 {
+                thread::yield_now();
+            }
+            let result =
+                std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| cv.wait(second.lock())));
+            assert!(result.is_err());
+            assert!(!second.is_locked());
+            *first.lock() = 1;
+            cv.notify_all();
+        });
+    }
 }
@@ -0,0 +1,402 @@
+use std::cell::UnsafeCell;
+use std::fmt;
+use std::io;
+use std::marker::{PhantomData, PhantomPinned};
+use std::mem::MaybeUninit;
+use std::ops::{Deref, DerefMut};
+use std::pin::Pin;
+use std::sync::Once;
+
+#[cfg(feature = "fallible")]
+use crate::LockError;
+
+// glibc's pthread_rwlockattr_setkind_np values. libc does not export
+// these.
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+const PTHREAD_RWLOCK_PREFER_WRITER_NONRECURSIVE_NP: libc::c_int = 2;
+
+/// Provides [structural
+/// pinning](https://doc.rust-lang.org/std/pin/index.html#projections-and-structural-pinning)
+/// atop `pthread_rwlock_t`.
+///
+/// POSIX forbids moving or copying an initialized rwlock, so the
+/// underlying lock is initialized in place on first use, after the
+/// PinnedRwLock has been pinned.
+pub struct PinnedRwLock<T> {
+    lock: UnsafeCell<MaybeUninit<libc::pthread_rwlock_t>>,
+    init: Once,
+    prefer_writer: bool,
+    data: UnsafeCell<T>,
+    /// The rwlock is initialized at its final address, so it must
+    /// stay put even when T is Unpin.
+    _pinned: PhantomPinned,
+}
+
+// SAFETY: pthread_rwlock_t provides the same guarantees as
+// std::sync::RwLock, so the same bounds apply.
+unsafe impl<T: Send> Send for PinnedRwLock<T> {}
+unsafe impl<T: Send + Sync> Sync for PinnedRwLock<T> {}
+
+impl<T> PinnedRwLock<T> {
+    /// Creates a lock with the platform's default reader/writer
+    /// policy.
+    pub fn new(init: T) -> Self {
+        Self::with_policy(init, false)
+    }
+
+    /// Creates a lock configured with
+    /// `PTHREAD_RWLOCK_PREFER_WRITER_NONRECURSIVE_NP`: once a writer
+    /// is waiting, new readers block until it has run, so writers
+    /// cannot be starved.
+    ///
+    /// As the name says, read locks are then not recursive. A thread
+    /// that already holds a read lock and calls `read` again while a
+    /// writer is waiting will deadlock.
+    #[cfg(all(target_os = "linux", target_env = "gnu"))]
+    pub fn new_prefer_writer(init: T) -> Self {
+        Self::with_policy(init, true)
+    }
+
+    fn with_policy(init: T, prefer_writer: bool) -> Self {
+        Self {
+            lock: UnsafeCell::new(MaybeUninit::uninit()),
+            init: Once::new(),
+            prefer_writer,
+            data: UnsafeCell::new(init),
+            _pinned: PhantomPinned,
+        }
+    }
+
+    fn raw(self: Pin<&Self>) -> *mut libc::pthread_rwlock_t {
+        let this = self.get_ref();
+        let raw = this.lock.get().cast::<libc::pthread_rwlock_t>();
+        this.init.call_once(|| unsafe {
+            // SAFETY: self is pinned, so raw is initialized at its
+            // final address.
+            let mut attr = MaybeUninit::<libc::pthread_rwlockattr_t>::uninit();
+            check(
+                "pthread_rwlockattr_init",
+                libc::pthread_rwlockattr_init(attr.as_mut_ptr()),
+            );
+            #[cfg(all(target_os = "linux", target_env = "gnu"))]
+            if this.prefer_writer {
+                check(
+                    "pthread_rwlockattr_setkind_np",
+                    libc::pthread_rwlockattr_setkind_np(
+                        attr.as_mut_ptr(),
+                        PTHREAD_RWLOCK_PREFER_WRITER_NONRECURSIVE_NP,
+                    ),
+                );
+            }
+            check(
+                "pthread_rwlock_init",
+                libc::pthread_rwlock_init(raw, attr.as_ptr()),
+            );
+            libc::pthread_rwlockattr_destroy(attr.as_mut_ptr());
+        });
+        raw
+    }
+
+    /// Acquires a shared read lock and returns a guard.
+    pub fn read(self: Pin<&Self>) -> PinnedRwLockReadGuard<'_, T> {
+        check("pthread_rwlock_rdlock", unsafe {
+            libc::pthread_rwlock_rdlock(self.raw())
+        });
+        PinnedRwLockReadGuard {
+            lock: self.get_ref(),
+            _not_send: PhantomData,
+        }
+    }
+
+    /// Attempts to acquire a shared read lock without blocking.
+    pub fn try_read(self: Pin<&Self>) -> Option<PinnedRwLockReadGuard<'_, T>> {
+        if unsafe { libc::pthread_rwlock_tryrdlock(self.raw()) } == 0 {
+            Some(PinnedRwLockReadGuard {
+                lock: self.get_ref(),
+                _not_send: PhantomData,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Acquires the exclusive write lock and returns a guard.
+    pub fn write(self: Pin<&Self>) -> PinnedRwLockWriteGuard<'_, T> {
+        check("pthread_rwlock_wrlock", unsafe {
+            libc::pthread_rwlock_wrlock(self.raw())
+        });
+        PinnedRwLockWriteGuard {
+            lock: self.get_ref(),
+            _not_send: PhantomData,
+        }
+    }
+
+    /// Like [read](Self::read), but returns the error reported by
+    /// `pthread_rwlock_rdlock` instead of panicking.
+    #[cfg_attr(docsrs, doc(cfg(feature = "fallible")))]
+    #[cfg(feature = "fallible")]
+    pub fn read_checked(self: Pin<&Self>) -> Result<PinnedRwLockReadGuard<'_, T>, LockError> {
+        checked(unsafe { libc::pthread_rwlock_rdlock(self.raw()) })?;
+        Ok(PinnedRwLockReadGuard {
+            lock: self.get_ref(),
+            _not_send: PhantomData,
+        })
+    }
+
+    /// Like [write](Self::write), but returns the error reported by
+    /// `pthread_rwlock_wrlock` instead of panicking.
+    #[cfg_attr(docsrs, doc(cfg(feature = "fallible")))]
+    #[cfg(feature = "fallible")]
+    pub fn write_checked(self: Pin<&Self>) -> Result<PinnedRwLockWriteGuard<'_, T>, LockError> {
+        checked(unsafe { libc::pthread_rwlock_wrlock(self.raw()) })?;
+        Ok(PinnedRwLockWriteGuard {
+            lock: self.get_ref(),
+            _not_send: PhantomData,
+        })
+    }
+
+    /// Attempts to acquire the exclusive write lock without blocking.
+    pub fn try_write(self: Pin<&Self>) -> Option<PinnedRwLockWriteGuard<'_, T>> {
+        if unsafe { libc::pthread_rwlock_trywrlock(self.raw()) } == 0 {
+            Some(PinnedRwLockWriteGuard {
+                lock: self.get_ref(),
+                _not_send: PhantomData,
+            })
+        } else {
+            None
+        }
+    }
+
+    fn unlock(&self) {
+        // SAFETY: only called by guards, which imply initialization.
+        let r = unsafe { libc::pthread_rwlock_unlock(self.lock.get().cast()) };
+        debug_assert_eq!(r, 0, "pthread_rwlock_unlock failed");
+    }
+}
+
+impl<T> Drop for PinnedRwLock<T> {
+    fn drop(&mut self) {
+        if !self.init.is_completed() {
+            return;
+        }
+        let raw = self.lock.get().cast();
+        // A guard passed to mem::forget leaves the lock held, and
+        // destroying a held rwlock is undefined behavior. Like std,
+        // leak the lock in that case.
+        // SAFETY: the lock was initialized, and no guards borrow it.
+        unsafe {
+            if libc::pthread_rwlock_trywrlock(raw) == 0 {
+                libc::pthread_rwlock_unlock(raw);
+                libc::pthread_rwlock_destroy(raw);
+            }
+        }
+    }
+}
+
+impl<T: Default> Default for PinnedRwLock<T> {
+    fn default() -> Self {
+        Self::new(Default::default())
+    }
+}
+
+impl<T> fmt::Debug for PinnedRwLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PinnedRwLock")
+            .field("prefer_writer", &self.prefer_writer)
+            .finish_non_exhaustive()
+    }
+}
+
+fn check(function: &str, result: libc::c_int) {
+    if result != 0 {
+        panic!(
+            "{} failed: {}",
+            function,
+            io::Error::from_raw_os_error(result)
+        );
+    }
+}
+
+#[cfg(feature = "fallible")]
+fn checked(result: libc::c_int) -> Result<(), LockError> {
+    match result {
+        0 => Ok(()),
+        libc::EDEADLK => Err(LockError::WouldDeadlock),
+        code => Err(LockError::Os(code)),
+    }
+}
+
+/// Shared access to a [PinnedRwLock]'s contents. [Deref] to `&T` is
+/// always possible.
+///
+/// `as_ref` projects structural pinning.
+pub struct PinnedRwLockReadGuard<'a, T: 'a> {
+    lock: &'a PinnedRwLock<T>,
+    _not_send: PhantomData<*mut ()>,
+}
+
+// SAFETY: the guard only hands out &T. It is not Send because the
+// rwlock must be released by the thread that acquired it.
+unsafe impl<'a, T: Sync> Sync for PinnedRwLockReadGuard<'a, T> {}
+
+impl<'a, T> PinnedRwLockReadGuard<'a, T> {
+    /// Provides pinned access to the underlying T.
+    pub fn as_ref(&self) -> Pin<&T> {
+        // PinnedRwLock::read requires the lock is pinned.
+        unsafe { Pin::new_unchecked(&*self.lock.data.get()) }
+    }
+}
+
+impl<'a, T> Deref for PinnedRwLockReadGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        // SAFETY: the guard holds a read lock.
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<'a, T> Drop for PinnedRwLockReadGuard<'a, T> {
+    fn drop(&mut self) {
+        self.lock.unlock();
+    }
+}
+
+/// Exclusive access to a [PinnedRwLock]'s contents. [Deref] to `&T`
+/// is always possible. [DerefMut] to `&mut T` is only possible if T
+/// is `Unpin`.
+///
+/// `as_ref` and `as_mut` project structural pinning.
+pub struct PinnedRwLockWriteGuard<'a, T: 'a> {
+    lock: &'a PinnedRwLock<T>,
+    _not_send: PhantomData<*mut ()>,
+}
+
+// SAFETY: as for the read guard.
+unsafe impl<'a, T: Sync> Sync for PinnedRwLockWriteGuard<'a, T> {}
+
+impl<'a, T> PinnedRwLockWriteGuard<'a, T> {
+    /// Provides pinned access to the underlying T.
+    pub fn as_ref(&self) -> Pin<&T> {
+        // PinnedRwLock::write requires the lock is pinned.
+        unsafe { Pin::new_unchecked(&*self.lock.data.get()) }
+    }
+
+    /// Provides pinned mutable access to the underlying T.
+    pub fn as_mut(&mut self) -> Pin<&mut T> {
+        // PinnedRwLock::write requires the lock is pinned.
+        // &mut self guarantees as_ref() cannot alias.
+        unsafe { Pin::new_unchecked(&mut *self.lock.data.get()) }
+    }
+}
+
+impl<'a, T> Deref for PinnedRwLockWriteGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        // SAFETY: the guard holds the write lock.
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<'a, T: Unpin> DerefMut for PinnedRwLockWriteGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        // SAFETY: the guard holds the write lock, and T is Unpin.
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<'a, T> Drop for PinnedRwLockWriteGuard<'a, T> {
+    fn drop(&mut self) {
+        self.lock.unlock();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pin_project::pin_project;
+    use std::{marker::PhantomPinned, pin::pin};
+
+    #[test]
+    fn mutate_through_write() {
+        let rw = pin!(PinnedRwLock::new(15));
+        *rw.as_ref().write() = 16;
+        assert_eq!(16, *rw.as_ref().read());
+    }
+
+    #[pin_project(UnsafeUnpin)]
+    struct MustPin {
+        value: u32,
+        pinned: PhantomPinned,
+    }
+
+    impl MustPin {
+        fn new() -> Self {
+            Self {
+                value: 0,
+                pinned: PhantomPinned,
+            }
+        }
+
+        fn inc(self: Pin<&mut Self>) -> u32 {
+            let value = self.project().value;
+            let prev = *value;
+            *value += 1;
+            prev
+        }
+
+        fn get(self: Pin<&Self>) -> u32 {
+            *self.project_ref().value
+        }
+    }
+
+    #[test]
+    fn pinned_method() {
+        let rw = pin!(PinnedRwLock::new(MustPin::new()));
+        let mut written = rw.as_ref().write();
+        assert_eq!(0, written.as_mut().inc());
+        assert_eq!(1, written.as_ref().get());
+        drop(written);
+        let a = rw.as_ref().read();
+        let b = rw.as_ref().read();
+        assert_eq!(a.as_ref().get(), b.as_ref().get());
+    }
+
+    #[test]
+    fn try_excludes_writer() {
+        let rw = pin!(PinnedRwLock::new(0));
+        let read = rw.as_ref().read();
+        assert!(rw.as_ref().try_read().is_some());
+        assert!(rw.as_ref().try_write().is_none());
+        drop(read);
+        let _write = rw.as_ref().try_write().unwrap();
+        assert!(rw.as_ref().try_read().is_none());
+    }
+
+    #[test]
+    fn drop_with_forgotten_guard() {
+        let rw = Box::pin(PinnedRwLock::new(0));
+        std::mem::forget(rw.as_ref().read());
+        drop(rw);
+        let rw = Box::pin(PinnedRwLock::new(0));
+        std::mem::forget(rw.as_ref().write());
+        drop(rw);
+    }
+
+    #[cfg(all(target_os = "linux", target_env = "gnu"))]
+    #[test]
+    fn waiting_writer_blocks_new_readers() {
+        use std::time::Duration;
+
+        let rw = pin!(PinnedRwLock::new_prefer_writer(0));
+        let rw = rw.as_ref();
+        std::thread::scope(|s| {
+            let read = rw.read();
+            s.spawn(move || *rw.write() += 1);
+            while rw.try_read().is_some() {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+            drop(read);
+        });
+        assert_eq!(1, *rw.read());
+    }
+}
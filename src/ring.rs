@@ -0,0 +1,317 @@
+use std::cell::UnsafeCell;
+use std::fmt;
+use std::mem::MaybeUninit;
+use std::ops::{Deref, DerefMut};
+use std::pin::Pin;
+
+use crate::std::{PinnedCondvar, PinnedMutex, PinnedMutexGuard};
+
+#[derive(Debug, Default)]
+struct RingState {
+    head: usize,
+    len: usize,
+    closed: bool,
+}
+
+/// A blocking single-producer single-consumer queue of `N` pinned
+/// slots.
+///
+/// The producer constructs each element directly in its slot, and the
+/// consumer accesses it there through `Pin<&mut T>`. Elements are
+/// dropped in place when the consumer releases them, so unlike a
+/// channel, nothing is ever moved in or out and `T` may be `!Unpin`.
+pub struct PinnedRing<T, const N: usize> {
+    slots: [UnsafeCell<MaybeUninit<T>>; N],
+    state: PinnedMutex<RingState>,
+    not_empty: PinnedCondvar,
+    not_full: PinnedCondvar,
+}
+
+// SAFETY: each slot is accessed by either the producer or the
+// consumer, as handed off under the state lock.
+unsafe impl<T: Send, const N: usize> Sync for PinnedRing<T, N> {}
+
+impl<T, const N: usize> PinnedRing<T, N> {
+    /// Panics if `N` is zero.
+    pub fn new() -> Self {
+        assert!(N > 0, "PinnedRing needs at least one slot");
+        Self {
+            slots: [(); N].map(|_| UnsafeCell::new(MaybeUninit::uninit())),
+            state: Default::default(),
+            not_empty: Default::default(),
+            not_full: Default::default(),
+        }
+    }
+
+    /// Splits the ring into its producer and consumer halves, which
+    /// may be sent to different threads. Dropping either half closes
+    /// the ring.
+    pub fn split(
+        self: Pin<&mut Self>,
+    ) -> (PinnedRingProducer<'_, T, N>, PinnedRingConsumer<'_, T, N>) {
+        let ring = self.into_ref().get_ref();
+        ring.lock().closed = false;
+        (PinnedRingProducer { ring }, PinnedRingConsumer { ring })
+    }
+
+    fn lock(&self) -> PinnedMutexGuard<'_, RingState> {
+        Pin::new(&self.state).lock()
+    }
+
+    fn close(&self) {
+        self.lock().closed = true;
+        self.not_empty.notify_all();
+        self.not_full.notify_all();
+    }
+
+    fn slot(&self, index: usize) -> *mut T {
+        self.slots[index].get().cast()
+    }
+}
+
+impl<T, const N: usize> Default for PinnedRing<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> Drop for PinnedRing<T, N> {
+    fn drop(&mut self) {
+        // Exclusive access means no guard exists, so read the state
+        // without locking. User code never runs under the state lock,
+        // so it cannot be poisoned.
+        let state = Pin::new(&mut self.state).get_pin_mut().get_mut();
+        let (head, len) = (state.head, state.len);
+        for i in 0..len {
+            // SAFETY: the slots between head and head + len are
+            // initialized and are dropped where they were pinned.
+            unsafe { self.slot((head + i) % N).drop_in_place() };
+        }
+    }
+}
+
+impl<T, const N: usize> fmt::Debug for PinnedRing<T, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PinnedRing")
+            .field("len", &self.lock().len)
+            .field("capacity", &N)
+            .finish()
+    }
+}
+
+/// The producing half of a [PinnedRing].
+pub struct PinnedRingProducer<'a, T, const N: usize> {
+    ring: &'a PinnedRing<T, N>,
+}
+
+impl<'a, T, const N: usize> PinnedRingProducer<'a, T, N> {
+    /// Waits for a free slot and constructs an element in it with
+    /// `init`. The element is never moved again.
+    ///
+    /// Returns false, without calling `init`, if the consumer has been
+    /// dropped.
+    pub fn push_with(&mut self, init: impl FnOnce() -> T) -> bool {
+        let ring = self.ring;
+        let state = ring
+            .not_full
+            .wait_while(ring.lock(), |s| s.len == N && !s.closed);
+        if state.closed {
+            return false;
+        }
+        let index = (state.head + state.len) % N;
+        drop(state);
+        // SAFETY: the slot is free, and only the producer fills slots.
+        unsafe { ring.slot(index).write(init()) };
+        ring.lock().len += 1;
+        ring.not_empty.notify_one();
+        true
+    }
+}
+
+impl<'a, T, const N: usize> Drop for PinnedRingProducer<'a, T, N> {
+    fn drop(&mut self) {
+        self.ring.close();
+    }
+}
+
+/// The consuming half of a [PinnedRing].
+pub struct PinnedRingConsumer<'a, T, const N: usize> {
+    ring: &'a PinnedRing<T, N>,
+}
+
+impl<'a, T, const N: usize> PinnedRingConsumer<'a, T, N> {
+    /// Waits for the next element and returns a guard over its slot.
+    /// Dropping the guard drops the element and frees the slot.
+    ///
+    /// Returns `None` once the producer has been dropped and every
+    /// element has been consumed.
+    pub fn pop(&mut self) -> Option<PinnedRingSlot<'_, T, N>> {
+        let ring = self.ring;
+        let state = ring
+            .not_empty
+            .wait_while(ring.lock(), |s| s.len == 0 && !s.closed);
+        if state.len == 0 {
+            return None;
+        }
+        Some(PinnedRingSlot {
+            ring,
+            index: state.head,
+        })
+    }
+}
+
+impl<'a, T, const N: usize> Drop for PinnedRingConsumer<'a, T, N> {
+    fn drop(&mut self) {
+        self.ring.close();
+    }
+}
+
+/// Provides access to an element of a [PinnedRing] in its slot.
+/// [Deref] to `&T` is always possible. [DerefMut] to `&mut T` is only
+/// possible if T is `Unpin`.
+///
+/// `as_ref` and `as_mut` project structural pinning.
+pub struct PinnedRingSlot<'a, T, const N: usize> {
+    ring: &'a PinnedRing<T, N>,
+    index: usize,
+}
+
+impl<'a, T, const N: usize> PinnedRingSlot<'a, T, N> {
+    /// Provides pinned access to the element.
+    pub fn as_ref(&self) -> Pin<&T> {
+        // SAFETY: the slot is initialized and owned by the consumer,
+        // and the ring is pinned by split.
+        unsafe { Pin::new_unchecked(&*self.ring.slot(self.index)) }
+    }
+
+    /// Provides pinned mutable access to the element.
+    pub fn as_mut(&mut self) -> Pin<&mut T> {
+        // SAFETY: as in as_ref. &mut self guarantees as_ref() cannot
+        // alias.
+        unsafe { Pin::new_unchecked(&mut *self.ring.slot(self.index)) }
+    }
+}
+
+impl<'a, T, const N: usize> Deref for PinnedRingSlot<'a, T, N> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        self.as_ref().get_ref()
+    }
+}
+
+impl<'a, T: Unpin, const N: usize> DerefMut for PinnedRingSlot<'a, T, N> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.as_mut().get_mut()
+    }
+}
+
+impl<'a, T, const N: usize> Drop for PinnedRingSlot<'a, T, N> {
+    fn drop(&mut self) {
+        // The slot must stay owned by the consumer until the element
+        // is gone, or the producer could refill it mid-drop. But if
+        // T::drop panics, the slot must still be freed, or it would be
+        // handed out and dropped again.
+        let _release = ReleaseSlot { ring: self.ring };
+        // SAFETY: the slot is initialized and owned by the consumer.
+        unsafe { self.ring.slot(self.index).drop_in_place() };
+    }
+}
+
+/// Frees the head slot when dropped, even while unwinding.
+struct ReleaseSlot<'a, T, const N: usize> {
+    ring: &'a PinnedRing<T, N>,
+}
+
+impl<'a, T, const N: usize> Drop for ReleaseSlot<'a, T, N> {
+    fn drop(&mut self) {
+        let mut state = self.ring.lock();
+        state.head = (state.head + 1) % N;
+        state.len -= 1;
+        drop(state);
+        self.ring.not_full.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pin_project::pin_project;
+    use std::{marker::PhantomPinned, pin::pin};
+
+    #[pin_project(UnsafeUnpin)]
+    struct Message {
+        seq: u32,
+        pinned: PhantomPinned,
+    }
+
+    #[test]
+    fn in_order_across_threads() {
+        let ring = pin!(PinnedRing::<Message, 4>::new());
+        let (mut producer, mut consumer) = ring.split();
+        std::thread::scope(|s| {
+            s.spawn(move || {
+                for seq in 0..100 {
+                    assert!(producer.push_with(|| Message {
+                        seq,
+                        pinned: PhantomPinned,
+                    }));
+                }
+            });
+            let mut expected = 0;
+            while let Some(mut slot) = consumer.pop() {
+                assert_eq!(expected, *slot.as_mut().project().seq);
+                expected += 1;
+            }
+            assert_eq!(100, expected);
+        });
+    }
+
+    #[test]
+    fn drops_unconsumed() {
+        let value = std::rc::Rc::new(());
+        let mut ring = Box::pin(PinnedRing::<_, 2>::new());
+        let (mut producer, consumer) = ring.as_mut().split();
+        assert!(producer.push_with(|| value.clone()));
+        drop(consumer);
+        assert!(!producer.push_with(|| unreachable!()));
+        drop(producer);
+        assert_eq!(2, std::rc::Rc::strong_count(&value));
+        drop(ring);
+        assert_eq!(1, std::rc::Rc::strong_count(&value));
+    }
+
+    struct PanicOnDrop {
+        explode: bool,
+        drops: std::rc::Rc<std::cell::Cell<u32>>,
+    }
+
+    impl Drop for PanicOnDrop {
+        fn drop(&mut self) {
+            self.drops.set(self.drops.get() + 1);
+            if self.explode {
+                panic!("element drop failed");
+            }
+        }
+    }
+
+    #[test]
+    fn panicking_drop_frees_slot() {
+        let drops = std::rc::Rc::new(std::cell::Cell::new(0));
+        let mut ring = Box::pin(PinnedRing::<_, 2>::new());
+        let (mut producer, mut consumer) = ring.as_mut().split();
+        for explode in [true, false] {
+            let drops = drops.clone();
+            assert!(producer.push_with(|| PanicOnDrop { explode, drops }));
+        }
+        let slot = consumer.pop().unwrap();
+        assert!(slot.explode);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| drop(slot)));
+        assert!(result.is_err());
+        assert_eq!(1, drops.get());
+        assert!(!consumer.pop().unwrap().explode);
+        assert_eq!(2, drops.get());
+        drop((producer, consumer));
+        drop(ring);
+        assert_eq!(2, drops.get());
+    }
+}
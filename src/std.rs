@@ -1,116 +1,2069 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::marker::PhantomPinned;
+use std::mem::ManuallyDrop;
 use std::ops::{Deref, DerefMut};
 use std::pin::Pin;
-use std::sync::{Condvar, Mutex, MutexGuard};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{
+    Arc, Condvar, Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard, TryLockError, Weak,
+};
+use std::thread::{self, Thread};
+use std::time::{Duration, Instant};
+
+use crate::instrument::{HoldTimer, Instrument};
+#[cfg(feature = "fallible")]
+use crate::LockError;
+use crate::{Deadline, NotifyPolicy};
 
 /// Provides [structural
 /// pinning](https://doc.rust-lang.org/std/pin/index.html#projections-and-structural-pinning)
 /// atop [Mutex].
-#[derive(Debug, Default)]
-pub struct PinnedMutex<T> {
+///
+/// `T` may be unsized. Build the mutex sized and let the pointer
+/// coerce: `Pin<Box<PinnedMutex<[u8; 4]>>>` converts to
+/// `Pin<Box<PinnedMutex<[u8]>>>`, and likewise for `dyn Trait`.
+#[derive(Default)]
+pub struct PinnedMutex<T: ?Sized> {
+    instrument: Instrument,
     inner: Mutex<T>,
 }
 
 impl<T> PinnedMutex<T> {
-    pub fn new(init: T) -> Self {
+    pub const fn new(init: T) -> Self {
         Self {
+            instrument: Instrument::new(),
             inner: Mutex::new(init),
         }
     }
 
-    /// Acquires the lock and returns a guard.
-    ///
-    /// Poisoning is not supported. If the underlying mutex is
-    /// poisoned, `lock` will panic.
-    pub fn lock(self: Pin<&Self>) -> PinnedMutexGuard<'_, T> {
-        let guard = self
-            .get_ref()
-            .inner
-            .lock()
-            .expect("PinnedMutex does not expose poison");
-        PinnedMutexGuard { guard }
+    /// Names this mutex for instrumentation. Only named mutexes are
+    /// instrumented, and without an instrumentation feature, the name
+    /// is ignored.
+    ///
+    /// With the `metrics` feature, named mutexes record
+    /// `pinned_mutex.acquire.wait_ns` and `pinned_mutex.hold_ns`
+    /// histograms labeled with `lock = name`. With the `tracy`
+    /// feature, they appear in Tracy's lock view under `name`.
+    pub fn named(mut self, name: &'static str) -> Self {
+        self.instrument = Instrument::named(name);
+        self
+    }
+
+    /// Wraps an existing [Mutex], moving it by value. Poison carries
+    /// over.
+    pub fn from_std(mutex: Mutex<T>) -> Self {
+        Self {
+            instrument: Instrument::new(),
+            inner: mutex,
+        }
+    }
+}
+
+impl<T: ?Sized> PinnedMutex<T> {
+    /// Pins a mutex that is never deallocated, such as a `static`.
+    /// Nothing can move out of a `'static` shared reference, so no
+    /// unsafe code or boxing is needed.
+    pub fn pin_static(&'static self) -> Pin<&'static Self> {
+        Pin::static_ref(self)
+    }
+
+    /// Acquires the lock and returns a guard.
+    ///
+    /// If the underlying mutex is poisoned, `lock` will panic. Use
+    /// [lock_result](Self::lock_result) to recover instead. The panic,
+    /// like every poison panic in this module, reports the caller's
+    /// location.
+    ///
+    /// In debug builds, panics if the current thread already holds
+    /// the lock, which would otherwise deadlock.
+    #[track_caller]
+    pub fn lock(self: Pin<&Self>) -> PinnedMutexGuard<'_, T> {
+        Owner::check(self.get_ref().addr());
+        let timer = self.get_ref().instrument.start_acquire();
+        let guard = self
+            .get_ref()
+            .inner
+            .lock()
+            .expect("PinnedMutex does not expose poison");
+        PinnedMutexGuard {
+            mutex: self,
+            guard,
+            hold: timer.acquired(),
+            owner: Owner::acquired(self.get_ref().addr()),
+        }
+    }
+
+    /// Acquires the lock, reporting poison instead of panicking. The
+    /// lock is held either way, and a [PinnedPoisonError] yields the
+    /// guard so the contents can be inspected or repaired.
+    #[track_caller]
+    pub fn lock_result(self: Pin<&Self>) -> PinnedLockResult<PinnedMutexGuard<'_, T>> {
+        Owner::check(self.get_ref().addr());
+        let timer = self.get_ref().instrument.start_acquire();
+        let (guard, poisoned) = match self.get_ref().inner.lock() {
+            Ok(guard) => (guard, false),
+            Err(poison) => (poison.into_inner(), true),
+        };
+        let guard = PinnedMutexGuard {
+            mutex: self,
+            guard,
+            hold: timer.acquired(),
+            owner: Owner::acquired(self.get_ref().addr()),
+        };
+        if poisoned {
+            Err(PinnedPoisonError { guard })
+        } else {
+            Ok(guard)
+        }
+    }
+
+    /// Like [lock](Self::lock), but returns [LockError::Poisoned]
+    /// instead of panicking if the underlying mutex is poisoned. In
+    /// debug builds, returns [LockError::WouldDeadlock] if the current
+    /// thread already holds the lock.
+    #[cfg_attr(docsrs, doc(cfg(feature = "fallible")))]
+    #[cfg(feature = "fallible")]
+    pub fn lock_checked(self: Pin<&Self>) -> Result<PinnedMutexGuard<'_, T>, LockError> {
+        if Owner::is_held(self.get_ref().addr()) {
+            return Err(LockError::WouldDeadlock);
+        }
+        self.lock_result().map_err(|_| LockError::Poisoned)
+    }
+
+    /// Returns a [PinnedMutexView] that locks this mutex but only
+    /// exposes the part of its contents selected by `project`.
+    pub fn view<U>(
+        self: Pin<&Self>,
+        project: fn(Pin<&mut T>) -> Pin<&mut U>,
+    ) -> PinnedMutexView<'_, T, U> {
+        PinnedMutexView {
+            mutex: self,
+            project,
+        }
+    }
+
+    /// Provides pinned mutable access to the contents without
+    /// locking. Exclusive access to the mutex proves that no guard
+    /// exists.
+    ///
+    /// This is `Mutex::get_mut`, renamed so it doesn't collide with
+    /// `Pin::get_mut`. Like [lock](Self::lock), it panics if the
+    /// underlying mutex is poisoned.
+    #[track_caller]
+    pub fn get_pin_mut(self: Pin<&mut Self>) -> Pin<&mut T> {
+        // SAFETY: the contents are structurally pinned.
+        unsafe {
+            self.map_unchecked_mut(|m| {
+                m.inner
+                    .get_mut()
+                    .expect("PinnedMutex does not expose poison")
+            })
+        }
+    }
+
+    /// Returns true if a thread panicked while holding the lock, and
+    /// the poison has not been cleared since.
+    pub fn is_poisoned(&self) -> bool {
+        self.inner.is_poisoned()
+    }
+
+    /// Clears the poison flag, declaring the contents consistent
+    /// again, so that [lock](Self::lock) stops panicking. Typically
+    /// called after repairing the contents through
+    /// [lock_result](Self::lock_result).
+    pub fn clear_poison(&self) {
+        self.inner.clear_poison();
+    }
+
+    /// Attempts to acquire the lock without blocking. Returns `None`
+    /// if another guard holds it.
+    ///
+    /// As with [lock](Self::lock), panics if the underlying mutex is
+    /// poisoned.
+    #[track_caller]
+    pub fn try_lock(self: Pin<&Self>) -> Option<PinnedMutexGuard<'_, T>> {
+        let guard = match self.get_ref().inner.try_lock() {
+            Ok(guard) => guard,
+            Err(TryLockError::WouldBlock) => return None,
+            Err(TryLockError::Poisoned(_)) => panic!("PinnedMutex does not expose poison"),
+        };
+        Some(PinnedMutexGuard {
+            mutex: self,
+            guard,
+            hold: HoldTimer::start(&self.get_ref().instrument),
+            owner: Owner::acquired(self.get_ref().addr()),
+        })
+    }
+
+    /// Locks the mutex, runs `f` with pinned access to the contents,
+    /// and unlocks it.
+    #[track_caller]
+    pub fn with_lock<R>(self: Pin<&Self>, f: impl FnOnce(Pin<&mut T>) -> R) -> R {
+        f(self.lock().as_mut())
+    }
+
+    /// Applies `f` to the pinned contents under the lock, for
+    /// fire-and-forget mutations such as in event handlers.
+    #[track_caller]
+    pub fn update(self: Pin<&Self>, f: impl FnOnce(Pin<&mut T>)) {
+        self.with_lock(f)
+    }
+
+    /// Like [update](Self::update), but returns what `f` returns. An
+    /// alias for [with_lock](Self::with_lock), named to pair with
+    /// `update`.
+    #[track_caller]
+    pub fn update_and<R>(self: Pin<&Self>, f: impl FnOnce(Pin<&mut T>) -> R) -> R {
+        self.with_lock(f)
+    }
+
+    /// Like [with_lock](Self::with_lock), but returns `None` without
+    /// calling `f` if the lock is held elsewhere.
+    #[track_caller]
+    pub fn try_with_lock<R>(self: Pin<&Self>, f: impl FnOnce(Pin<&mut T>) -> R) -> Option<R> {
+        self.try_lock().map(|mut guard| f(guard.as_mut()))
+    }
+
+    /// Acquires the lock, giving up after `timeout`, to bound how
+    /// long a soft-realtime caller can block.
+    ///
+    /// std's Mutex has no timed lock, and a waiter queue beside it
+    /// would cost every mutex, so this is layered on
+    /// [try_lock](Self::try_lock): it polls with a backoff that starts
+    /// at a microsecond and doubles up to a millisecond, sleeping no
+    /// later than the deadline. Acquisition is therefore not fair, and
+    /// under contention may return up to a millisecond after the lock
+    /// became free. Prefer the `parking_lot` backend where lock
+    /// timeouts are common.
+    ///
+    /// Like [try_lock](Self::try_lock), panics if the underlying mutex
+    /// is poisoned.
+    #[track_caller]
+    pub fn lock_timeout(self: Pin<&Self>, timeout: Duration) -> Option<PinnedMutexGuard<'_, T>> {
+        self.try_lock_deadline(timeout)
+    }
+
+    fn addr(&self) -> usize {
+        self as *const Self as *const () as usize
+    }
+
+    /// Attempts to acquire the lock, blocking until at most
+    /// `deadline`, which may be a [Duration], an [Instant], or
+    /// [Deadline::Never]. Returns `None` if the deadline passed first.
+    /// Waits by polling, as described for
+    /// [lock_timeout](Self::lock_timeout), and panics on poison the
+    /// same way.
+    #[track_caller]
+    pub fn try_lock_deadline(
+        self: Pin<&Self>,
+        deadline: impl Into<Deadline>,
+    ) -> Option<PinnedMutexGuard<'_, T>> {
+        let at = match deadline.into().to_instant() {
+            Some(at) => at,
+            None => return Some(self.lock()),
+        };
+        // std's Mutex has no timed lock, so poll try_lock, sleeping
+        // a little longer each time, up to a millisecond.
+        let mut backoff = Duration::from_micros(1);
+        loop {
+            if let Some(guard) = self.try_lock() {
+                return Some(guard);
+            }
+            let now = Instant::now();
+            if now >= at {
+                return None;
+            }
+            thread::sleep(backoff.min(at - now));
+            backoff = (backoff * 2).min(Duration::from_millis(1));
+        }
+    }
+}
+
+impl<T: ?Sized + 'static> PinnedMutex<T> {
+    /// Like [lock](Self::lock), but the guard holds a reference count
+    /// instead of a borrow, so it is `'static` and can be stored or
+    /// captured by callbacks. Like any std mutex guard, it must be
+    /// dropped on the thread that locked it.
+    #[track_caller]
+    pub fn lock_owned(self: Pin<Arc<Self>>) -> PinnedMutexOwnedGuard<T> {
+        // SAFETY: the owned guard holds the Arc until after the
+        // borrowed guard is dropped.
+        let mutex = unsafe { crate::pin_arc_static(&self) };
+        PinnedMutexOwnedGuard {
+            guard: mutex.lock(),
+            _mutex: self,
+        }
+    }
+
+    /// Like [try_lock](Self::try_lock), but returns an owned guard as
+    /// in [lock_owned](Self::lock_owned).
+    #[track_caller]
+    pub fn try_lock_owned(self: Pin<Arc<Self>>) -> Option<PinnedMutexOwnedGuard<T>> {
+        // SAFETY: as in lock_owned.
+        let mutex = unsafe { crate::pin_arc_static(&self) };
+        Some(PinnedMutexOwnedGuard {
+            guard: mutex.try_lock()?,
+            _mutex: self,
+        })
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for PinnedMutex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Lock inner directly. Debug does not require pinning, and
+        // should not show up in lock instrumentation.
+        let mut d = f.debug_struct("PinnedMutex");
+        match self.inner.try_lock() {
+            Ok(guard) => d.field("data", &&*guard),
+            Err(TryLockError::Poisoned(poison)) => d.field("data", &&*poison.into_inner()),
+            Err(TryLockError::WouldBlock) => d.field("data", &format_args!("<locked>")),
+        };
+        d.field("poisoned", &self.inner.is_poisoned());
+        d.finish_non_exhaustive()
+    }
+}
+
+impl<T: Clone> PinnedMutex<T> {
+    /// Locks briefly and returns a copy of the contents, for readers
+    /// that only need a snapshot.
+    ///
+    /// If the underlying mutex is poisoned, `get_cloned` will panic.
+    #[track_caller]
+    pub fn get_cloned(self: Pin<&Self>) -> T {
+        self.lock().clone()
+    }
+}
+
+/// Locks the source and clones its contents into a new, unlocked,
+/// unnamed mutex. Cloning a poisoned mutex panics.
+impl<T: Clone> Clone for PinnedMutex<T> {
+    fn clone(&self) -> Self {
+        // Lock inner directly, as in Debug. Cloning does not require
+        // pinning the source.
+        let guard = self
+            .inner
+            .lock()
+            .expect("PinnedMutex does not expose poison");
+        Self::new(guard.clone())
+    }
+}
+
+/// Compares contents, locking both mutexes. To avoid deadlocking
+/// with a concurrent comparison in the other direction, the mutex at
+/// the lower address is always locked first. Comparing a mutex with
+/// itself locks it once. Comparing a poisoned mutex panics.
+impl<T: ?Sized + PartialEq> PartialEq for PinnedMutex<T> {
+    fn eq(&self, other: &Self) -> bool {
+        // Lock inner directly, as in Debug. Comparison does not
+        // require pinning.
+        fn lock<T: ?Sized>(m: &PinnedMutex<T>) -> MutexGuard<'_, T> {
+            m.inner.lock().expect("PinnedMutex does not expose poison")
+        }
+        if std::ptr::addr_eq(self, other) {
+            let guard = lock(self);
+            // T's PartialEq need not be reflexive, as with NaN.
+            #[allow(clippy::eq_op)]
+            return *guard == *guard;
+        }
+        let self_lower = (self as *const Self).cast::<()>() < (other as *const Self).cast();
+        let (first, second) = if self_lower {
+            (self, other)
+        } else {
+            (other, self)
+        };
+        let first = lock(first);
+        let second = lock(second);
+        *first == *second
+    }
+}
+
+impl<T: ?Sized + Eq> Eq for PinnedMutex<T> {}
+
+impl<T> From<T> for PinnedMutex<T> {
+    fn from(init: T) -> Self {
+        Self::new(init)
+    }
+}
+
+impl<T: Unpin> PinnedMutex<T> {
+    /// Consumes the mutex and returns its contents, which may move
+    /// because T is `Unpin`. Like [lock](Self::lock), panics if the
+    /// underlying mutex is poisoned.
+    #[track_caller]
+    pub fn into_inner(self) -> T {
+        self.inner
+            .into_inner()
+            .expect("PinnedMutex does not expose poison")
+    }
+
+    /// Consumes the mutex and returns the underlying [Mutex] by value,
+    /// including any poison.
+    pub fn into_std(self) -> Mutex<T> {
+        self.inner
+    }
+
+    /// Like [into_inner](Self::into_inner), but reports poison
+    /// instead of panicking. The contents can still be recovered from
+    /// the error.
+    pub fn into_inner_result(self) -> Result<T, PinnedPoisonError<T>> {
+        self.inner.into_inner().map_err(|poison| PinnedPoisonError {
+            guard: poison.into_inner(),
+        })
+    }
+
+    /// Locks the mutex and replaces its contents with `value`.
+    #[track_caller]
+    pub fn set(self: Pin<&Self>, value: T) {
+        *self.lock() = value;
+    }
+
+    /// Locks the mutex, replaces its contents with `value`, and
+    /// returns the old contents.
+    #[track_caller]
+    pub fn replace(self: Pin<&Self>, value: T) -> T {
+        std::mem::replace(&mut *self.lock(), value)
+    }
+
+    /// Swaps the contents of two mutexes.
+    ///
+    /// Both locks are taken in address order, so concurrent swaps of
+    /// the same pair in opposite directions cannot deadlock. Swapping
+    /// a mutex with itself does nothing.
+    #[track_caller]
+    pub fn swap(self: Pin<&Self>, other: Pin<&Self>) {
+        if std::ptr::eq(self.get_ref(), other.get_ref()) {
+            return;
+        }
+        let (first, second) = if (self.get_ref() as *const Self) < other.get_ref() {
+            (self, other)
+        } else {
+            (other, self)
+        };
+        let mut a = first.lock();
+        let mut b = second.lock();
+        std::mem::swap(&mut *a, &mut *b);
+    }
+
+    /// Swaps the contents of two mutexes if both can be locked without
+    /// blocking. Returns whether the swap happened. Swapping a mutex
+    /// with itself does nothing and succeeds.
+    pub fn try_swap(self: Pin<&Self>, other: Pin<&Self>) -> bool {
+        if std::ptr::eq(self.get_ref(), other.get_ref()) {
+            return true;
+        }
+        match (self.try_lock(), other.try_lock()) {
+            (Some(mut a), Some(mut b)) => {
+                std::mem::swap(&mut *a, &mut *b);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+impl<T: Unpin + Default> PinnedMutex<T> {
+    /// Locks the mutex, replaces its contents with the default, and
+    /// returns the old contents.
+    #[track_caller]
+    pub fn take(self: Pin<&Self>) -> T {
+        std::mem::take(&mut *self.lock())
+    }
+}
+
+#[cfg(debug_assertions)]
+thread_local! {
+    /// The addresses of the PinnedMutexes this thread holds.
+    static HELD_MUTEXES: RefCell<Vec<usize>> = const { RefCell::new(Vec::new()) };
+}
+
+/// In debug builds, records that the current thread holds a
+/// [PinnedMutex], so that locking it again panics instead of
+/// deadlocking. Compiles to nothing in release builds.
+///
+/// Guards are !Send, so an Owner is dropped on the thread that
+/// created it.
+#[derive(Debug)]
+struct Owner {
+    #[cfg(debug_assertions)]
+    addr: usize,
+}
+
+#[cfg_attr(not(debug_assertions), allow(unused_variables))]
+impl Owner {
+    /// Returns whether the current thread holds the mutex at `addr`.
+    /// Always false in release builds.
+    fn is_held(addr: usize) -> bool {
+        #[cfg(debug_assertions)]
+        return HELD_MUTEXES.with(|held| held.borrow().contains(&addr));
+        #[cfg(not(debug_assertions))]
+        false
+    }
+
+    /// Panics if the current thread holds the mutex at `addr`.
+    #[track_caller]
+    fn check(addr: usize) {
+        assert!(
+            !Self::is_held(addr),
+            "PinnedMutex is already locked by the current thread"
+        );
+    }
+
+    fn acquired(addr: usize) -> Self {
+        #[cfg(debug_assertions)]
+        HELD_MUTEXES.with(|held| held.borrow_mut().push(addr));
+        Self {
+            #[cfg(debug_assertions)]
+            addr,
+        }
+    }
+}
+
+impl Drop for Owner {
+    fn drop(&mut self) {
+        #[cfg(debug_assertions)]
+        HELD_MUTEXES.with(|held| {
+            let mut held = held.borrow_mut();
+            if let Some(i) = held.iter().rposition(|&addr| addr == self.addr) {
+                held.swap_remove(i);
+            }
+        });
+    }
+}
+
+/// Provides access to mutex's contents. [Deref] to `&T` is always
+/// possible. [DerefMut] to `&mut T` is only possive if T is `Unpin`.
+///
+/// `as_ref` and `as_mut` project structural pinning.
+pub struct PinnedMutexGuard<'a, T: ?Sized + 'a> {
+    mutex: Pin<&'a PinnedMutex<T>>,
+    guard: MutexGuard<'a, T>,
+    hold: HoldTimer<'a>,
+    owner: Owner,
+}
+
+impl<'a, T: ?Sized> PinnedMutexGuard<'a, T> {
+    /// Provides pinned access to the underlying T.
+    pub fn as_ref(&self) -> Pin<&T> {
+        // PinnedMutex::lock requires the mutex is pinned.
+        unsafe { Pin::new_unchecked(&self.guard) }
+    }
+
+    /// Provides pinned mutable access to the underlying T.
+    pub fn as_mut(&mut self) -> Pin<&mut T> {
+        // PinnedMutex::lock requires the mutex is pinned.
+        // &mut self guarantees as_ref() cannot alias.
+        unsafe { Pin::new_unchecked(&mut self.guard) }
+    }
+
+    /// Returns the mutex this guard locks, so the guard can be dropped
+    /// and the same mutex locked again later.
+    pub fn mutex(&self) -> Pin<&'a PinnedMutex<T>> {
+        self.mutex
+    }
+
+    /// Releases the lock. Equivalent to dropping the guard, but says
+    /// so explicitly.
+    pub fn unlock(self) {
+        drop(self)
+    }
+
+    /// Narrows the guard to the part of the contents selected by `f`,
+    /// keeping the lock held.
+    pub fn map<U: ?Sized>(
+        mut self,
+        f: impl FnOnce(Pin<&mut T>) -> Pin<&mut U>,
+    ) -> MappedPinnedMutexGuard<'a, T, U> {
+        // SAFETY: the projection stays pinned, because the mapped
+        // guard only hands it out pinned.
+        let data: *mut U = unsafe { f(self.as_mut()).get_unchecked_mut() };
+        MappedPinnedMutexGuard { _guard: self, data }
+    }
+
+    /// Like [map](Self::map), but `f` may decline, in which case the
+    /// original guard is returned.
+    pub fn try_map<U: ?Sized>(
+        mut self,
+        f: impl FnOnce(Pin<&mut T>) -> Option<Pin<&mut U>>,
+    ) -> Result<MappedPinnedMutexGuard<'a, T, U>, Self> {
+        match f(self.as_mut()) {
+            Some(projected) => {
+                // SAFETY: as in map.
+                let data: *mut U = unsafe { projected.get_unchecked_mut() };
+                Ok(MappedPinnedMutexGuard { _guard: self, data })
+            }
+            None => Err(self),
+        }
+    }
+}
+
+impl<'a, T: ?Sized> Deref for PinnedMutexGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl<'a, T: ?Sized + Unpin> DerefMut for PinnedMutexGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        // SAFETY: T is Unpin, so it's safe to move out of T.
+        &mut self.guard
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Debug> fmt::Debug for PinnedMutexGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Display> fmt::Display for PinnedMutexGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+/// A [PinnedMutexGuard] narrowed to part of the contents by
+/// [map](PinnedMutexGuard::map) or [PinnedMutexView::lock]. [Deref]
+/// to `&U` is always possible. [DerefMut] to `&mut U` is only
+/// possible if U is `Unpin`.
+///
+/// `as_ref` and `as_mut` project structural pinning.
+pub struct MappedPinnedMutexGuard<'a, T: ?Sized + 'a, U: ?Sized> {
+    /// Holds the lock.
+    _guard: PinnedMutexGuard<'a, T>,
+    /// Points into the locked contents, which do not move when the
+    /// guard does.
+    data: *mut U,
+}
+
+// SAFETY: the guard provides shared access to U, like &U.
+unsafe impl<'a, T: ?Sized + Sync, U: ?Sized + Sync> Sync for MappedPinnedMutexGuard<'a, T, U> {}
+
+impl<'a, T: ?Sized, U: ?Sized> MappedPinnedMutexGuard<'a, T, U> {
+    /// Provides pinned access to the projected U.
+    pub fn as_ref(&self) -> Pin<&U> {
+        // SAFETY: map pinned the projection, and the lock is held.
+        unsafe { Pin::new_unchecked(&*self.data) }
+    }
+
+    /// Provides pinned mutable access to the projected U.
+    pub fn as_mut(&mut self) -> Pin<&mut U> {
+        // SAFETY: as in as_ref. &mut self guarantees as_ref() cannot
+        // alias.
+        unsafe { Pin::new_unchecked(&mut *self.data) }
+    }
+}
+
+impl<'a, T: ?Sized, U: ?Sized> Deref for MappedPinnedMutexGuard<'a, T, U> {
+    type Target = U;
+    fn deref(&self) -> &Self::Target {
+        self.as_ref().get_ref()
+    }
+}
+
+impl<'a, T: ?Sized, U: ?Sized + Unpin> DerefMut for MappedPinnedMutexGuard<'a, T, U> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.as_mut().get_mut()
+    }
+}
+
+impl<'a, T: ?Sized, U: ?Sized + fmt::Debug> fmt::Debug for MappedPinnedMutexGuard<'a, T, U> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<'a, T: ?Sized, U: ?Sized + fmt::Display> fmt::Display for MappedPinnedMutexGuard<'a, T, U> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+/// A [PinnedMutexGuard] that keeps its mutex alive, returned by
+/// [PinnedMutex::lock_owned].
+pub struct PinnedMutexOwnedGuard<T: ?Sized + 'static> {
+    /// Borrows from `_mutex`, so it is declared first to drop first.
+    guard: PinnedMutexGuard<'static, T>,
+    _mutex: Pin<Arc<PinnedMutex<T>>>,
+}
+
+impl<T: ?Sized> PinnedMutexOwnedGuard<T> {
+    /// Provides pinned access to the underlying T.
+    pub fn as_ref(&self) -> Pin<&T> {
+        self.guard.as_ref()
+    }
+
+    /// Provides pinned mutable access to the underlying T.
+    pub fn as_mut(&mut self) -> Pin<&mut T> {
+        self.guard.as_mut()
+    }
+}
+
+impl<T: ?Sized> Deref for PinnedMutexOwnedGuard<T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl<T: ?Sized + Unpin> DerefMut for PinnedMutexOwnedGuard<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.guard
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for PinnedMutexOwnedGuard<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: ?Sized + fmt::Display> fmt::Display for PinnedMutexOwnedGuard<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+/// The result of [PinnedMutex::lock_result] and the other
+/// poison-reporting lock methods.
+pub type PinnedLockResult<G> = Result<G, PinnedPoisonError<G>>;
+
+/// Reports that a lock's previous holder panicked. It carries what
+/// the operation would otherwise have returned: the guard from
+/// [PinnedMutex::lock_result], which still holds the lock, or the
+/// contents from [PinnedMutex::into_inner_result].
+pub struct PinnedPoisonError<G> {
+    guard: G,
+}
+
+impl<G> PinnedPoisonError<G> {
+    /// Returns the recovered value.
+    pub fn into_inner(self) -> G {
+        self.guard
+    }
+
+    /// Returns a reference to the recovered value.
+    pub fn get_ref(&self) -> &G {
+        &self.guard
+    }
+
+    /// Returns a mutable reference to the recovered value.
+    pub fn get_mut(&mut self) -> &mut G {
+        &mut self.guard
+    }
+}
+
+impl<G> fmt::Debug for PinnedPoisonError<G> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PinnedPoisonError").finish_non_exhaustive()
+    }
+}
+
+impl<G> fmt::Display for PinnedPoisonError<G> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("poisoned lock: another thread panicked while holding it")
+    }
+}
+
+impl<G> std::error::Error for PinnedPoisonError<G> {}
+
+/// A lock handle over one projected part of a [PinnedMutex]'s
+/// contents, created by [PinnedMutex::view].
+///
+/// Locking the view locks the whole parent mutex, but the guard only
+/// exposes the projected `U`. Views are `Copy`, so they can be handed
+/// to subsystems as a capability for exactly that part of the state.
+pub struct PinnedMutexView<'a, T: ?Sized, U> {
+    mutex: Pin<&'a PinnedMutex<T>>,
+    project: fn(Pin<&mut T>) -> Pin<&mut U>,
+}
+
+impl<'a, T: ?Sized, U> Clone for PinnedMutexView<'a, T, U> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, T: ?Sized, U> Copy for PinnedMutexView<'a, T, U> {}
+
+impl<'a, T: ?Sized, U> PinnedMutexView<'a, T, U> {
+    /// Locks the parent mutex and returns a guard over the projected
+    /// part.
+    #[track_caller]
+    pub fn lock(&self) -> MappedPinnedMutexGuard<'a, T, U> {
+        self.mutex.lock().map(self.project)
+    }
+}
+
+impl<T: Send> PinnedMutex<T> {
+    /// Runs `f` inside [thread::scope], passing a [PinnedMutexScope]
+    /// that spawns workers sharing this mutex.
+    pub fn scope<'env, R>(
+        self: Pin<&'env Self>,
+        f: impl for<'scope> FnOnce(PinnedMutexScope<'scope, 'env, T>) -> R,
+    ) -> R {
+        thread::scope(|scope| f(PinnedMutexScope { scope, mutex: self }))
+    }
+}
+
+/// A [thread::Scope] paired with a pinned mutex, created by
+/// [PinnedMutex::scope]. It is `Copy`, and each spawned worker
+/// receives its own copy, so no pinned reference needs to be captured
+/// by hand.
+pub struct PinnedMutexScope<'scope, 'env, T> {
+    scope: &'scope thread::Scope<'scope, 'env>,
+    mutex: Pin<&'env PinnedMutex<T>>,
+}
+
+impl<'scope, 'env, T> Clone for PinnedMutexScope<'scope, 'env, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'scope, 'env, T> Copy for PinnedMutexScope<'scope, 'env, T> {}
+
+impl<'scope, 'env, T: Send> PinnedMutexScope<'scope, 'env, T> {
+    /// Spawns a scoped thread that receives a copy of this scope.
+    pub fn spawn<F, R>(&self, f: F) -> thread::ScopedJoinHandle<'scope, R>
+    where
+        F: FnOnce(Self) -> R + Send + 'scope,
+        R: Send + 'scope,
+    {
+        let this = *self;
+        self.scope.spawn(move || f(this))
+    }
+
+    /// Returns the shared mutex.
+    pub fn mutex(&self) -> Pin<&'env PinnedMutex<T>> {
+        self.mutex
+    }
+
+    /// Locks the shared mutex.
+    #[track_caller]
+    pub fn lock(&self) -> PinnedMutexGuard<'env, T> {
+        self.mutex.lock()
+    }
+
+    /// Locks the shared mutex, runs `f` with pinned access to the
+    /// contents, and unlocks it.
+    #[track_caller]
+    pub fn with_lock<R>(&self, f: impl FnOnce(Pin<&mut T>) -> R) -> R {
+        f(self.lock().as_mut())
+    }
+
+    /// Returns the underlying [thread::Scope].
+    pub fn thread_scope(&self) -> &'scope thread::Scope<'scope, 'env> {
+        self.scope
+    }
+}
+
+/// A reference-counted, pinned [PinnedMutex], for state shared
+/// across threads without a scope. Cloning it shares the same mutex,
+/// and the mutex is dropped with the last clone.
+///
+/// It is `Pin<Arc<PinnedMutex<T>>>` with the locking methods
+/// forwarded, so call sites need not spell out the layers.
+#[derive(Debug)]
+pub struct PinnedShared<T> {
+    /// Always pinned. It is only ever created by `Arc::pin` or
+    /// upgraded from a weak reference to one.
+    mutex: Arc<PinnedMutex<T>>,
+}
+
+impl<T> Clone for PinnedShared<T> {
+    fn clone(&self) -> Self {
+        Self {
+            mutex: self.mutex.clone(),
+        }
+    }
+}
+
+impl<T: Default> Default for PinnedShared<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T> PinnedShared<T> {
+    pub fn new(init: T) -> Self {
+        Self::from(Arc::pin(PinnedMutex::new(init)))
+    }
+
+    /// Returns the shared mutex.
+    pub fn mutex(&self) -> Pin<&PinnedMutex<T>> {
+        // SAFETY: mutex is always pinned.
+        unsafe { Pin::new_unchecked(&*self.mutex) }
+    }
+
+    /// Locks the shared mutex.
+    #[track_caller]
+    pub fn lock(&self) -> PinnedMutexGuard<'_, T> {
+        self.mutex().lock()
+    }
+
+    /// Locks the shared mutex, runs `f` with pinned access to the
+    /// contents, and unlocks it.
+    #[track_caller]
+    pub fn with_lock<R>(&self, f: impl FnOnce(Pin<&mut T>) -> R) -> R {
+        f(self.lock().as_mut())
+    }
+
+    /// Returns a weak reference that does not keep the mutex alive.
+    pub fn downgrade(&self) -> PinnedWeak<T> {
+        PinnedWeak {
+            mutex: Arc::downgrade(&self.mutex),
+        }
+    }
+
+    /// Returns true if both refer to the same mutex.
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.mutex, &other.mutex)
+    }
+}
+
+impl<T: Send + 'static> PinnedShared<T> {
+    /// Spawns a thread that receives its own clone.
+    pub fn spawn<F, R>(&self, f: F) -> thread::JoinHandle<R>
+    where
+        F: FnOnce(Self) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let this = self.clone();
+        thread::spawn(move || f(this))
+    }
+}
+
+impl<T> From<Pin<Arc<PinnedMutex<T>>>> for PinnedShared<T> {
+    fn from(mutex: Pin<Arc<PinnedMutex<T>>>) -> Self {
+        Self {
+            // SAFETY: the Arc is only ever handed out pinned again.
+            mutex: unsafe { Pin::into_inner_unchecked(mutex) },
+        }
+    }
+}
+
+impl<T> From<PinnedShared<T>> for Pin<Arc<PinnedMutex<T>>> {
+    fn from(shared: PinnedShared<T>) -> Self {
+        // SAFETY: mutex is always pinned.
+        unsafe { Pin::new_unchecked(shared.mutex) }
+    }
+}
+
+/// A weak reference to a [PinnedShared]'s mutex, created by
+/// [PinnedShared::downgrade].
+#[derive(Debug)]
+pub struct PinnedWeak<T> {
+    mutex: Weak<PinnedMutex<T>>,
+}
+
+impl<T> Clone for PinnedWeak<T> {
+    fn clone(&self) -> Self {
+        Self {
+            mutex: self.mutex.clone(),
+        }
+    }
+}
+
+impl<T> PinnedWeak<T> {
+    /// Returns the mutex, if any [PinnedShared] still keeps it alive.
+    pub fn upgrade(&self) -> Option<PinnedShared<T>> {
+        self.mutex.upgrade().map(|mutex| PinnedShared { mutex })
+    }
+}
+
+/// Provides [structural
+/// pinning](https://doc.rust-lang.org/std/pin/index.html#projections-and-structural-pinning)
+/// atop [RwLock], for pinned state that is read far more often than
+/// it is written.
+#[derive(Debug, Default)]
+pub struct PinnedRwLock<T: ?Sized> {
+    inner: RwLock<T>,
+}
+
+impl<T> PinnedRwLock<T> {
+    pub const fn new(init: T) -> Self {
+        Self {
+            inner: RwLock::new(init),
+        }
+    }
+}
+
+impl<T: ?Sized> PinnedRwLock<T> {
+    /// Acquires a shared read lock and returns a guard.
+    ///
+    /// If the underlying lock is poisoned, `read` will panic.
+    #[track_caller]
+    pub fn read(self: Pin<&Self>) -> PinnedRwLockReadGuard<'_, T> {
+        PinnedRwLockReadGuard {
+            guard: self
+                .get_ref()
+                .inner
+                .read()
+                .expect("PinnedRwLock does not expose poison"),
+        }
+    }
+
+    /// Acquires the exclusive write lock and returns a guard.
+    ///
+    /// If the underlying lock is poisoned, `write` will panic.
+    #[track_caller]
+    pub fn write(self: Pin<&Self>) -> PinnedRwLockWriteGuard<'_, T> {
+        PinnedRwLockWriteGuard {
+            guard: self
+                .get_ref()
+                .inner
+                .write()
+                .expect("PinnedRwLock does not expose poison"),
+        }
+    }
+
+    /// Acquires a shared read lock, reporting poison instead of
+    /// panicking. The lock is held either way, and a
+    /// [PinnedPoisonError] yields the guard.
+    pub fn read_result(self: Pin<&Self>) -> PinnedLockResult<PinnedRwLockReadGuard<'_, T>> {
+        match self.get_ref().inner.read() {
+            Ok(guard) => Ok(PinnedRwLockReadGuard { guard }),
+            Err(poison) => Err(PinnedPoisonError {
+                guard: PinnedRwLockReadGuard {
+                    guard: poison.into_inner(),
+                },
+            }),
+        }
+    }
+
+    /// Acquires the exclusive write lock, reporting poison instead of
+    /// panicking. The lock is held either way, and a
+    /// [PinnedPoisonError] yields the guard so the contents can be
+    /// repaired.
+    pub fn write_result(self: Pin<&Self>) -> PinnedLockResult<PinnedRwLockWriteGuard<'_, T>> {
+        match self.get_ref().inner.write() {
+            Ok(guard) => Ok(PinnedRwLockWriteGuard { guard }),
+            Err(poison) => Err(PinnedPoisonError {
+                guard: PinnedRwLockWriteGuard {
+                    guard: poison.into_inner(),
+                },
+            }),
+        }
+    }
+
+    /// Like [read](Self::read), but returns [LockError::Poisoned]
+    /// instead of panicking if the underlying lock is poisoned.
+    #[cfg_attr(docsrs, doc(cfg(feature = "fallible")))]
+    #[cfg(feature = "fallible")]
+    pub fn read_checked(self: Pin<&Self>) -> Result<PinnedRwLockReadGuard<'_, T>, LockError> {
+        self.read_result().map_err(|_| LockError::Poisoned)
+    }
+
+    /// Like [write](Self::write), but returns [LockError::Poisoned]
+    /// instead of panicking if the underlying lock is poisoned.
+    #[cfg_attr(docsrs, doc(cfg(feature = "fallible")))]
+    #[cfg(feature = "fallible")]
+    pub fn write_checked(self: Pin<&Self>) -> Result<PinnedRwLockWriteGuard<'_, T>, LockError> {
+        self.write_result().map_err(|_| LockError::Poisoned)
+    }
+
+    /// Returns true if a writer panicked while holding the lock, and
+    /// the poison has not been cleared since.
+    pub fn is_poisoned(&self) -> bool {
+        self.inner.is_poisoned()
+    }
+
+    /// Clears the poison flag, declaring the contents consistent
+    /// again, so that [read](Self::read) and [write](Self::write)
+    /// stop panicking.
+    pub fn clear_poison(&self) {
+        self.inner.clear_poison();
+    }
+
+    /// Attempts to acquire a shared read lock without blocking.
+    /// Returns `None` if a writer holds the lock.
+    ///
+    /// As with [read](Self::read), panics if the underlying lock is
+    /// poisoned.
+    #[track_caller]
+    pub fn try_read(self: Pin<&Self>) -> Option<PinnedRwLockReadGuard<'_, T>> {
+        match self.get_ref().inner.try_read() {
+            Ok(guard) => Some(PinnedRwLockReadGuard { guard }),
+            Err(TryLockError::WouldBlock) => None,
+            Err(TryLockError::Poisoned(_)) => panic!("PinnedRwLock does not expose poison"),
+        }
+    }
+
+    /// Attempts to acquire the exclusive write lock without blocking.
+    /// Returns `None` if any reader or writer holds the lock.
+    ///
+    /// As with [write](Self::write), panics if the underlying lock is
+    /// poisoned.
+    #[track_caller]
+    pub fn try_write(self: Pin<&Self>) -> Option<PinnedRwLockWriteGuard<'_, T>> {
+        match self.get_ref().inner.try_write() {
+            Ok(guard) => Some(PinnedRwLockWriteGuard { guard }),
+            Err(TryLockError::WouldBlock) => None,
+            Err(TryLockError::Poisoned(_)) => panic!("PinnedRwLock does not expose poison"),
+        }
+    }
+}
+
+/// Shared access to a [PinnedRwLock]'s contents. [Deref] to `&T` is
+/// always possible.
+///
+/// `as_ref` projects structural pinning.
+pub struct PinnedRwLockReadGuard<'a, T: ?Sized + 'a> {
+    guard: RwLockReadGuard<'a, T>,
+}
+
+impl<'a, T: ?Sized> PinnedRwLockReadGuard<'a, T> {
+    /// Provides pinned access to the underlying T.
+    pub fn as_ref(&self) -> Pin<&T> {
+        // PinnedRwLock::read requires the lock is pinned.
+        unsafe { Pin::new_unchecked(&self.guard) }
+    }
+
+    /// Narrows the guard to the part of the contents selected by `f`,
+    /// keeping the read lock held.
+    pub fn map<U: ?Sized>(
+        self,
+        f: impl FnOnce(Pin<&T>) -> Pin<&U>,
+    ) -> MappedPinnedRwLockReadGuard<'a, T, U> {
+        let data: *const U = f(self.as_ref()).get_ref();
+        MappedPinnedRwLockReadGuard { _guard: self, data }
+    }
+
+    /// Like [map](Self::map), but `f` may decline, in which case the
+    /// original guard is returned.
+    pub fn try_map<U: ?Sized>(
+        self,
+        f: impl FnOnce(Pin<&T>) -> Option<Pin<&U>>,
+    ) -> Result<MappedPinnedRwLockReadGuard<'a, T, U>, Self> {
+        match f(self.as_ref()) {
+            Some(projected) => {
+                let data: *const U = projected.get_ref();
+                Ok(MappedPinnedRwLockReadGuard { _guard: self, data })
+            }
+            None => Err(self),
+        }
+    }
+}
+
+impl<'a, T: ?Sized> Deref for PinnedRwLockReadGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Debug> fmt::Debug for PinnedRwLockReadGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Display> fmt::Display for PinnedRwLockReadGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+/// Exclusive access to a [PinnedRwLock]'s contents. [Deref] to `&T`
+/// is always possible. [DerefMut] to `&mut T` is only possible if T
+/// is `Unpin`.
+///
+/// `as_ref` and `as_mut` project structural pinning.
+pub struct PinnedRwLockWriteGuard<'a, T: ?Sized + 'a> {
+    guard: RwLockWriteGuard<'a, T>,
+}
+
+impl<'a, T: ?Sized> PinnedRwLockWriteGuard<'a, T> {
+    /// Provides pinned access to the underlying T.
+    pub fn as_ref(&self) -> Pin<&T> {
+        // PinnedRwLock::write requires the lock is pinned.
+        unsafe { Pin::new_unchecked(&self.guard) }
+    }
+
+    /// Provides pinned mutable access to the underlying T.
+    pub fn as_mut(&mut self) -> Pin<&mut T> {
+        // PinnedRwLock::write requires the lock is pinned.
+        // &mut self guarantees as_ref() cannot alias.
+        unsafe { Pin::new_unchecked(&mut self.guard) }
+    }
+
+    /// Narrows the guard to the part of the contents selected by `f`,
+    /// keeping the write lock held.
+    pub fn map<U: ?Sized>(
+        mut self,
+        f: impl FnOnce(Pin<&mut T>) -> Pin<&mut U>,
+    ) -> MappedPinnedRwLockWriteGuard<'a, T, U> {
+        // SAFETY: the projection stays pinned, because the mapped
+        // guard only hands it out pinned.
+        let data: *mut U = unsafe { f(self.as_mut()).get_unchecked_mut() };
+        MappedPinnedRwLockWriteGuard { _guard: self, data }
+    }
+
+    /// Like [map](Self::map), but `f` may decline, in which case the
+    /// original guard is returned.
+    pub fn try_map<U: ?Sized>(
+        mut self,
+        f: impl FnOnce(Pin<&mut T>) -> Option<Pin<&mut U>>,
+    ) -> Result<MappedPinnedRwLockWriteGuard<'a, T, U>, Self> {
+        match f(self.as_mut()) {
+            Some(projected) => {
+                // SAFETY: as in map.
+                let data: *mut U = unsafe { projected.get_unchecked_mut() };
+                Ok(MappedPinnedRwLockWriteGuard { _guard: self, data })
+            }
+            None => Err(self),
+        }
+    }
+}
+
+impl<'a, T: ?Sized> Deref for PinnedRwLockWriteGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl<'a, T: ?Sized + Unpin> DerefMut for PinnedRwLockWriteGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.guard
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Debug> fmt::Debug for PinnedRwLockWriteGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Display> fmt::Display for PinnedRwLockWriteGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+/// A [PinnedRwLockReadGuard] narrowed to part of the contents by
+/// [map](PinnedRwLockReadGuard::map). [Deref] to `&U` is always
+/// possible.
+///
+/// `as_ref` projects structural pinning.
+pub struct MappedPinnedRwLockReadGuard<'a, T: ?Sized + 'a, U: ?Sized> {
+    /// Holds the read lock.
+    _guard: PinnedRwLockReadGuard<'a, T>,
+    /// Points into the locked contents.
+    data: *const U,
+}
+
+// SAFETY: the guard provides shared access to U, like &U.
+unsafe impl<'a, T: ?Sized + Sync, U: ?Sized + Sync> Sync for MappedPinnedRwLockReadGuard<'a, T, U> {}
+
+impl<'a, T: ?Sized, U: ?Sized> MappedPinnedRwLockReadGuard<'a, T, U> {
+    /// Provides pinned access to the projected U.
+    pub fn as_ref(&self) -> Pin<&U> {
+        // SAFETY: map pinned the projection, and the lock is held.
+        unsafe { Pin::new_unchecked(&*self.data) }
+    }
+}
+
+impl<'a, T: ?Sized, U: ?Sized> Deref for MappedPinnedRwLockReadGuard<'a, T, U> {
+    type Target = U;
+    fn deref(&self) -> &Self::Target {
+        self.as_ref().get_ref()
+    }
+}
+
+impl<'a, T: ?Sized, U: ?Sized + fmt::Debug> fmt::Debug for MappedPinnedRwLockReadGuard<'a, T, U> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<'a, T: ?Sized, U: ?Sized + fmt::Display> fmt::Display
+    for MappedPinnedRwLockReadGuard<'a, T, U>
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+/// A [PinnedRwLockWriteGuard] narrowed to part of the contents by
+/// [map](PinnedRwLockWriteGuard::map). [Deref] to `&U` is always
+/// possible. [DerefMut] to `&mut U` is only possible if U is `Unpin`.
+///
+/// `as_ref` and `as_mut` project structural pinning.
+pub struct MappedPinnedRwLockWriteGuard<'a, T: ?Sized + 'a, U: ?Sized> {
+    /// Holds the write lock.
+    _guard: PinnedRwLockWriteGuard<'a, T>,
+    /// Points into the locked contents, which do not move when the
+    /// guard does.
+    data: *mut U,
+}
+
+// SAFETY: the guard provides shared access to U, like &U.
+unsafe impl<'a, T: ?Sized + Sync, U: ?Sized + Sync> Sync
+    for MappedPinnedRwLockWriteGuard<'a, T, U>
+{
+}
+
+impl<'a, T: ?Sized, U: ?Sized> MappedPinnedRwLockWriteGuard<'a, T, U> {
+    /// Provides pinned access to the projected U.
+    pub fn as_ref(&self) -> Pin<&U> {
+        // SAFETY: map pinned the projection, and the lock is held.
+        unsafe { Pin::new_unchecked(&*self.data) }
+    }
+
+    /// Provides pinned mutable access to the projected U.
+    pub fn as_mut(&mut self) -> Pin<&mut U> {
+        // SAFETY: as in as_ref. &mut self guarantees as_ref() cannot
+        // alias.
+        unsafe { Pin::new_unchecked(&mut *self.data) }
+    }
+}
+
+impl<'a, T: ?Sized, U: ?Sized> Deref for MappedPinnedRwLockWriteGuard<'a, T, U> {
+    type Target = U;
+    fn deref(&self) -> &Self::Target {
+        self.as_ref().get_ref()
+    }
+}
+
+impl<'a, T: ?Sized, U: ?Sized + Unpin> DerefMut for MappedPinnedRwLockWriteGuard<'a, T, U> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.as_mut().get_mut()
+    }
+}
+
+impl<'a, T: ?Sized, U: ?Sized + fmt::Debug> fmt::Debug for MappedPinnedRwLockWriteGuard<'a, T, U> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<'a, T: ?Sized, U: ?Sized + fmt::Display> fmt::Display
+    for MappedPinnedRwLockWriteGuard<'a, T, U>
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+/// Provides [structural
+/// pinning](https://doc.rust-lang.org/std/pin/index.html#projections-and-structural-pinning)
+/// atop [ReentrantLock](std::sync::ReentrantLock), which the thread
+/// holding it may acquire again, as in callbacks that reenter the
+/// code that invoked them.
+///
+/// Several guards on one thread may be alive at once, so guards only
+/// provide shared access: [Deref] to `&T` and pinned `as_ref`. Use
+/// interior mutability, like [Cell](std::cell::Cell) or
+/// [RefCell], to mutate the contents.
+#[cfg_attr(docsrs, doc(cfg(feature = "nightly")))]
+#[cfg(feature = "nightly")]
+#[derive(Debug, Default)]
+pub struct PinnedReentrantLock<T: ?Sized> {
+    inner: std::sync::ReentrantLock<T>,
+}
+
+#[cfg(feature = "nightly")]
+impl<T> PinnedReentrantLock<T> {
+    pub const fn new(init: T) -> Self {
+        Self {
+            inner: std::sync::ReentrantLock::new(init),
+        }
+    }
+}
+
+#[cfg(feature = "nightly")]
+impl<T: ?Sized> PinnedReentrantLock<T> {
+    /// Acquires the lock, blocking unless the current thread already
+    /// holds it, and returns a guard.
+    pub fn lock(self: Pin<&Self>) -> PinnedReentrantLockGuard<'_, T> {
+        PinnedReentrantLockGuard {
+            guard: self.get_ref().inner.lock(),
+        }
+    }
+
+    /// Like [lock](Self::lock), for code generic over backends. Always
+    /// succeeds: the lock is not poisoned by panics, and the current
+    /// thread may already hold it.
+    #[cfg_attr(docsrs, doc(cfg(feature = "fallible")))]
+    #[cfg(feature = "fallible")]
+    pub fn lock_checked(self: Pin<&Self>) -> Result<PinnedReentrantLockGuard<'_, T>, LockError> {
+        Ok(self.lock())
+    }
+}
+
+/// Provides shared access to a [PinnedReentrantLock]'s contents.
+/// [Deref] to `&T` is always possible.
+///
+/// `as_ref` projects structural pinning.
+#[cfg_attr(docsrs, doc(cfg(feature = "nightly")))]
+#[cfg(feature = "nightly")]
+pub struct PinnedReentrantLockGuard<'a, T: ?Sized + 'a> {
+    guard: std::sync::ReentrantLockGuard<'a, T>,
+}
+
+#[cfg(feature = "nightly")]
+impl<'a, T: ?Sized> PinnedReentrantLockGuard<'a, T> {
+    /// Provides pinned access to the underlying T.
+    pub fn as_ref(&self) -> Pin<&T> {
+        // PinnedReentrantLock::lock requires the lock is pinned.
+        unsafe { Pin::new_unchecked(&self.guard) }
+    }
+}
+
+#[cfg(feature = "nightly")]
+impl<'a, T: ?Sized> Deref for PinnedReentrantLockGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+#[cfg(feature = "nightly")]
+impl<'a, T: ?Sized + fmt::Debug> fmt::Debug for PinnedReentrantLockGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+#[cfg(feature = "nightly")]
+impl<'a, T: ?Sized + fmt::Display> fmt::Display for PinnedReentrantLockGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct PinnedCondvar {
+    condvar: Condvar,
+    epoch: AtomicUsize,
+}
+
+impl PinnedCondvar {
+    pub fn new() -> PinnedCondvar {
+        Default::default()
+    }
+
+    #[track_caller]
+    pub fn wait<'a, T>(&self, guard: PinnedMutexGuard<'a, T>) -> PinnedMutexGuard<'a, T> {
+        let PinnedMutexGuard {
+            mutex,
+            guard,
+            hold,
+            owner,
+        } = guard;
+        hold.finish();
+        PinnedMutexGuard {
+            mutex,
+            guard: self
+                .condvar
+                .wait(guard)
+                .expect("PinnedMutex does not expose poison"),
+            hold: HoldTimer::start(&mutex.get_ref().instrument),
+            owner,
+        }
+    }
+
+    #[track_caller]
+    pub fn wait_while<'a, T, F>(
+        &self,
+        guard: PinnedMutexGuard<'a, T>,
+        mut condition: F,
+    ) -> PinnedMutexGuard<'a, T>
+    where
+        F: FnMut(Pin<&mut T>) -> bool,
+    {
+        let PinnedMutexGuard {
+            mutex,
+            guard,
+            hold,
+            owner,
+        } = guard;
+        hold.finish();
+        PinnedMutexGuard {
+            mutex,
+            guard: self
+                .condvar
+                .wait_while(guard, move |v| {
+                    // SAFETY: v is never moved.
+                    condition(unsafe { Pin::new_unchecked(v) })
+                })
+                .expect("PinnedMutex does not expose poison"),
+            hold: HoldTimer::start(&mutex.get_ref().instrument),
+            owner,
+        }
+    }
+
+    /// Like [wait](Self::wait), but gives up at `deadline`. Returns the
+    /// guard and whether the deadline passed.
+    #[track_caller]
+    pub fn wait_deadline<'a, T>(
+        &self,
+        guard: PinnedMutexGuard<'a, T>,
+        deadline: impl Into<Deadline>,
+    ) -> (PinnedMutexGuard<'a, T>, bool) {
+        let PinnedMutexGuard {
+            mutex,
+            guard,
+            hold,
+            owner,
+        } = guard;
+        hold.finish();
+        let (guard, timed_out) = match deadline.into().to_instant() {
+            None => (
+                self.condvar
+                    .wait(guard)
+                    .expect("PinnedMutex does not expose poison"),
+                false,
+            ),
+            Some(at) => {
+                let timeout = at.saturating_duration_since(Instant::now());
+                let (guard, result) = self
+                    .condvar
+                    .wait_timeout(guard, timeout)
+                    .expect("PinnedMutex does not expose poison");
+                (guard, result.timed_out())
+            }
+        };
+        let guard = PinnedMutexGuard {
+            mutex,
+            guard,
+            hold: HoldTimer::start(&mutex.get_ref().instrument),
+            owner,
+        };
+        (guard, timed_out)
+    }
+
+    /// Like [wait_while](Self::wait_while), but gives up at
+    /// `deadline`. Returns the guard and whether the deadline passed
+    /// with `condition` still true.
+    #[track_caller]
+    pub fn wait_while_deadline<'a, T, F>(
+        &self,
+        mut guard: PinnedMutexGuard<'a, T>,
+        deadline: impl Into<Deadline>,
+        mut condition: F,
+    ) -> (PinnedMutexGuard<'a, T>, bool)
+    where
+        F: FnMut(Pin<&mut T>) -> bool,
+    {
+        let deadline = Deadline::from(deadline.into().to_instant());
+        while condition(guard.as_mut()) {
+            let (next, timed_out) = self.wait_deadline(guard, deadline);
+            guard = next;
+            if timed_out {
+                let still = condition(guard.as_mut());
+                return (guard, still);
+            }
+        }
+        (guard, false)
+    }
+
+    /// Returns the number of notifications sent so far, wrapping on
+    /// overflow. Record it before releasing the lock, and pass it to
+    /// [wait_for_epoch](Self::wait_for_epoch) to wait for a
+    /// notification sent after that point.
+    pub fn epoch(&self) -> usize {
+        self.epoch.load(Ordering::SeqCst)
+    }
+
+    /// Blocks until a notification has been sent since
+    /// [epoch](Self::epoch) returned `epoch`, or returns immediately if
+    /// one already has.
+    ///
+    /// A notification is never missed if it is sent while holding the
+    /// mutex, the same contract as any condvar predicate.
+    #[track_caller]
+    pub fn wait_for_epoch<'a, T>(
+        &self,
+        guard: PinnedMutexGuard<'a, T>,
+        epoch: usize,
+    ) -> PinnedMutexGuard<'a, T> {
+        self.wait_while(guard, |_| self.epoch() == epoch)
+    }
+
+    pub fn notify_one(&self) {
+        self.epoch.fetch_add(1, Ordering::SeqCst);
+        self.condvar.notify_one()
+    }
+
+    pub fn notify_all(&self) {
+        self.epoch.fetch_add(1, Ordering::SeqCst);
+        self.condvar.notify_all()
+    }
+}
+
+/// A condition variable whose waiters register a priority.
+/// `notify_one` wakes the highest-priority waiter, and waiters of
+/// equal priority wake in the order they started waiting.
+///
+/// Each waiting thread parks on a pinned node on its own stack, which
+/// is linked into the condvar's queue until it is notified.
+#[derive(Debug, Default)]
+pub struct PinnedPriorityCondvar {
+    queue: WaitQueue,
+}
+
+impl PinnedPriorityCondvar {
+    pub fn new() -> PinnedPriorityCondvar {
+        Default::default()
+    }
+
+    /// Releases the guard's lock and blocks until notified, then
+    /// reacquires the lock. Higher `priority` values are woken first.
+    pub fn wait<'a, T>(
+        &self,
+        guard: PinnedMutexGuard<'a, T>,
+        priority: u32,
+    ) -> PinnedMutexGuard<'a, T> {
+        self.queue.wait(guard, priority)
+    }
+
+    pub fn wait_while<'a, T, F>(
+        &self,
+        mut guard: PinnedMutexGuard<'a, T>,
+        priority: u32,
+        mut condition: F,
+    ) -> PinnedMutexGuard<'a, T>
+    where
+        F: FnMut(Pin<&mut T>) -> bool,
+    {
+        while condition(guard.as_mut()) {
+            guard = self.queue.wait(guard, priority);
+        }
+        guard
+    }
+
+    /// Wakes the highest-priority waiter, if any.
+    pub fn notify_one(&self) {
+        self.queue.notify_one();
+    }
+
+    /// Wakes every waiter, highest priority first.
+    pub fn notify_all(&self) {
+        self.queue.notify_all();
+    }
+}
+
+/// A condition variable whose `notify_one` wakes waiters in strict
+/// FIFO order: the thread that has waited longest is always woken
+/// first. [PinnedCondvar] makes no such promise.
+///
+/// Like [PinnedPriorityCondvar], each waiting thread parks on a pinned
+/// node on its own stack, queued in arrival order.
+#[derive(Debug, Default)]
+pub struct PinnedFifoCondvar {
+    queue: WaitQueue,
+}
+
+impl PinnedFifoCondvar {
+    pub fn new() -> PinnedFifoCondvar {
+        Default::default()
+    }
+
+    /// Releases the guard's lock and blocks until notified, then
+    /// reacquires the lock.
+    pub fn wait<'a, T>(&self, guard: PinnedMutexGuard<'a, T>) -> PinnedMutexGuard<'a, T> {
+        self.queue.wait(guard, 0)
+    }
+
+    pub fn wait_while<'a, T, F>(
+        &self,
+        mut guard: PinnedMutexGuard<'a, T>,
+        mut condition: F,
+    ) -> PinnedMutexGuard<'a, T>
+    where
+        F: FnMut(Pin<&mut T>) -> bool,
+    {
+        while condition(guard.as_mut()) {
+            guard = self.queue.wait(guard, 0);
+        }
+        guard
+    }
+
+    /// Wakes the longest-waiting thread, if any.
+    pub fn notify_one(&self) {
+        self.queue.notify_one();
+    }
+
+    /// Wakes every waiter, in the order they started waiting.
+    pub fn notify_all(&self) {
+        self.queue.notify_all();
+    }
+}
+
+/// A parked thread's node. Lives on the waiter's stack, pinned, while
+/// the queue holds a pointer to it.
+struct Waiter {
+    thread: Thread,
+    notified: AtomicBool,
+    _pinned: PhantomPinned,
+}
+
+#[derive(Debug, Default)]
+struct WaitQueue {
+    /// Sorted by descending priority, then by arrival.
+    waiters: Mutex<Vec<(u32, *const Waiter)>>,
+}
+
+// SAFETY: the raw pointers refer to Waiters that stay alive, and are
+// only dereferenced, until they are removed from the queue and
+// notified.
+unsafe impl Send for WaitQueue {}
+unsafe impl Sync for WaitQueue {}
+
+impl WaitQueue {
+    fn wait<'a, T>(
+        &self,
+        guard: PinnedMutexGuard<'a, T>,
+        priority: u32,
+    ) -> PinnedMutexGuard<'a, T> {
+        let node = Waiter {
+            thread: thread::current(),
+            notified: AtomicBool::new(false),
+            _pinned: PhantomPinned,
+        };
+        // SAFETY: node is shadowed and never moved again.
+        let node = unsafe { Pin::new_unchecked(&node) };
+        {
+            let mut waiters = self.lock();
+            let index = waiters.partition_point(|&(p, _)| p >= priority);
+            waiters.insert(index, (priority, &*node as *const Waiter));
+        }
+        // The node is queued before the lock is released, so a
+        // notification sent after this point cannot be lost.
+        let mutex = guard.mutex;
+        drop(guard);
+        while !node.notified.load(Ordering::Acquire) {
+            thread::park();
+        }
+        mutex.lock()
+    }
+
+    fn notify_one(&self) {
+        let waiter = {
+            let mut waiters = self.lock();
+            if waiters.is_empty() {
+                return;
+            }
+            waiters.remove(0).1
+        };
+        // SAFETY: waiter was just dequeued and has not been notified.
+        unsafe { Self::wake(waiter) };
+    }
+
+    fn notify_all(&self) {
+        let waiters = std::mem::take(&mut *self.lock());
+        for (_, waiter) in waiters {
+            // SAFETY: as in notify_one.
+            unsafe { Self::wake(waiter) };
+        }
+    }
+
+    /// # Safety
+    ///
+    /// `waiter` must have been removed from the queue, and not yet
+    /// notified.
+    unsafe fn wake(waiter: *const Waiter) {
+        // The waiter may return and free its node as soon as
+        // notified is set, so clone its thread handle first.
+        let thread = (*waiter).thread.clone();
+        (*waiter).notified.store(true, Ordering::Release);
+        thread.unpark();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<(u32, *const Waiter)>> {
+        // The queue is never left inconsistent by a panic.
+        self.waiters.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// A [PinnedMutex] that enforces a lock ordering. Each mutex has a
+/// `LEVEL`, and a thread may only acquire a mutex whose level is
+/// strictly lower than every hierarchical mutex it already holds.
+/// Violations panic instead of risking a deadlock.
+#[derive(Debug, Default)]
+pub struct HierarchicalPinnedMutex<T, const LEVEL: u32> {
+    inner: PinnedMutex<T>,
+}
+
+thread_local! {
+    static HELD_LEVELS: RefCell<Vec<u32>> = const { RefCell::new(Vec::new()) };
+}
+
+impl<T, const LEVEL: u32> HierarchicalPinnedMutex<T, LEVEL> {
+    pub fn new(init: T) -> Self {
+        Self {
+            inner: PinnedMutex::new(init),
+        }
+    }
+
+    /// Acquires the lock and returns a guard.
+    ///
+    /// Panics if the current thread holds a hierarchical mutex whose
+    /// level is not greater than `LEVEL`.
+    #[track_caller]
+    pub fn lock(self: Pin<&Self>) -> HierarchicalPinnedMutexGuard<'_, T, LEVEL> {
+        if let Some(lowest) = Self::violated_level() {
+            panic!(
+                "lock order violation: acquiring level {} while holding level {}",
+                LEVEL, lowest
+            );
+        }
+        let guard = self.inner().lock();
+        HELD_LEVELS.with(|held| held.borrow_mut().push(LEVEL));
+        HierarchicalPinnedMutexGuard { guard }
+    }
+
+    /// Like [lock](Self::lock), but returns [LockError::WouldDeadlock]
+    /// instead of panicking on a lock order violation, and otherwise
+    /// fails as [PinnedMutex::lock_checked] does.
+    #[cfg_attr(docsrs, doc(cfg(feature = "fallible")))]
+    #[cfg(feature = "fallible")]
+    pub fn lock_checked(
+        self: Pin<&Self>,
+    ) -> Result<HierarchicalPinnedMutexGuard<'_, T, LEVEL>, LockError> {
+        if Self::violated_level().is_some() {
+            return Err(LockError::WouldDeadlock);
+        }
+        let guard = self.inner().lock_checked()?;
+        HELD_LEVELS.with(|held| held.borrow_mut().push(LEVEL));
+        Ok(HierarchicalPinnedMutexGuard { guard })
+    }
+
+    /// Returns the lowest level the current thread holds, if taking
+    /// `LEVEL` now would break the ordering.
+    fn violated_level() -> Option<u32> {
+        HELD_LEVELS.with(|held| {
+            let lowest = held.borrow().iter().copied().min()?;
+            (LEVEL >= lowest).then_some(lowest)
+        })
+    }
+
+    fn inner(self: Pin<&Self>) -> Pin<&PinnedMutex<T>> {
+        // SAFETY: inner is structurally pinned.
+        unsafe { self.map_unchecked(|m| &m.inner) }
     }
 }
 
-/// Provides access to mutex's contents. [Deref] to `&T` is always
-/// possible. [DerefMut] to `&mut T` is only possive if T is `Unpin`.
-///
-/// `as_ref` and `as_mut` project structural pinning.
+/// Provides access to a [HierarchicalPinnedMutex]'s contents, like
+/// [PinnedMutexGuard]. Dropping it releases the mutex's level.
 #[derive(Debug)]
-pub struct PinnedMutexGuard<'a, T: 'a> {
-    guard: MutexGuard<'a, T>,
+pub struct HierarchicalPinnedMutexGuard<'a, T: 'a, const LEVEL: u32> {
+    guard: PinnedMutexGuard<'a, T>,
 }
 
-impl<'a, T> PinnedMutexGuard<'a, T> {
+impl<'a, T, const LEVEL: u32> HierarchicalPinnedMutexGuard<'a, T, LEVEL> {
     /// Provides pinned access to the underlying T.
     pub fn as_ref(&self) -> Pin<&T> {
-        // PinnedMutex::lock requires the mutex is pinned.
-        unsafe { Pin::new_unchecked(&self.guard) }
+        self.guard.as_ref()
     }
 
     /// Provides pinned mutable access to the underlying T.
     pub fn as_mut(&mut self) -> Pin<&mut T> {
-        // PinnedMutex::lock requires the mutex is pinned.
-        // &mut self guarantees as_ref() cannot alias.
-        unsafe { Pin::new_unchecked(&mut self.guard) }
+        self.guard.as_mut()
     }
 }
 
-impl<'a, T> Deref for PinnedMutexGuard<'a, T> {
+impl<'a, T, const LEVEL: u32> Deref for HierarchicalPinnedMutexGuard<'a, T, LEVEL> {
     type Target = T;
     fn deref(&self) -> &Self::Target {
         &self.guard
     }
 }
 
-impl<'a, T: Unpin> DerefMut for PinnedMutexGuard<'a, T> {
+impl<'a, T: Unpin, const LEVEL: u32> DerefMut for HierarchicalPinnedMutexGuard<'a, T, LEVEL> {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        // SAFETY: T is Unpin, so it's safe to move out of T.
         &mut self.guard
     }
 }
 
+impl<'a, T, const LEVEL: u32> Drop for HierarchicalPinnedMutexGuard<'a, T, LEVEL> {
+    fn drop(&mut self) {
+        // Guards are !Send, so this runs on the thread that pushed LEVEL.
+        HELD_LEVELS.with(|held| {
+            let mut held = held.borrow_mut();
+            if let Some(i) = held.iter().rposition(|&level| level == LEVEL) {
+                held.remove(i);
+            }
+        });
+    }
+}
+
+/// A [PinnedMutex] paired with the [PinnedCondvar] that signals
+/// changes to its contents.
+///
+/// [lock_notify](Self::lock_notify) returns a [NotifyingGuard], which
+/// notifies the condvar itself when it is dropped after a mutation,
+/// so an update can't forget to wake its waiters.
 #[derive(Debug, Default)]
-pub struct PinnedCondvar(Condvar);
+pub struct PinnedMonitor<T> {
+    mutex: PinnedMutex<T>,
+    condvar: PinnedCondvar,
+}
 
-impl PinnedCondvar {
-    pub fn new() -> PinnedCondvar {
-        Default::default()
+impl<T> PinnedMonitor<T> {
+    pub fn new(init: T) -> Self {
+        Self {
+            mutex: PinnedMutex::new(init),
+            condvar: PinnedCondvar::new(),
+        }
     }
 
-    pub fn wait<'a, T>(&self, guard: PinnedMutexGuard<'a, T>) -> PinnedMutexGuard<'a, T> {
-        PinnedMutexGuard {
-            guard: self
-                .0
-                .wait(guard.guard)
-                .expect("PinnedMutex does not expose poison"),
+    /// Returns the pinned mutex.
+    pub fn mutex(self: Pin<&Self>) -> Pin<&PinnedMutex<T>> {
+        // SAFETY: mutex is structurally pinned.
+        unsafe { self.map_unchecked(|m| &m.mutex) }
+    }
+
+    /// Returns the condvar.
+    pub fn condvar(&self) -> &PinnedCondvar {
+        &self.condvar
+    }
+
+    /// Acquires the lock without arranging any notification.
+    #[track_caller]
+    pub fn lock(self: Pin<&Self>) -> PinnedMutexGuard<'_, T> {
+        self.mutex().lock()
+    }
+
+    /// Acquires the lock and returns a guard that notifies the condvar
+    /// according to `policy` when dropped, if the contents were
+    /// mutably accessed through it.
+    #[track_caller]
+    pub fn lock_notify(self: Pin<&Self>, policy: NotifyPolicy) -> NotifyingGuard<'_, T> {
+        NotifyingGuard {
+            guard: ManuallyDrop::new(self.lock()),
+            condvar: &self.get_ref().condvar,
+            policy,
+            mutated: false,
         }
     }
 
-    pub fn wait_while<'a, T, F>(
+    /// Acquires the lock, giving up after `timeout`. See
+    /// [PinnedMutex::lock_timeout] for how std's backend waits.
+    #[track_caller]
+    pub fn lock_timeout(self: Pin<&Self>, timeout: Duration) -> Option<PinnedMutexGuard<'_, T>> {
+        self.mutex().lock_timeout(timeout)
+    }
+
+    /// Acquires the lock within `timeout`, passes the contents to `f`,
+    /// and notifies the condvar according to `policy` after releasing
+    /// the lock. Returns `None`, without calling `f`, if the lock was
+    /// not acquired in time.
+    pub fn update_and_notify_within<R>(
+        self: Pin<&Self>,
+        timeout: Duration,
+        policy: NotifyPolicy,
+        f: impl FnOnce(Pin<&mut T>) -> R,
+    ) -> Option<R> {
+        let mut guard = NotifyingGuard {
+            guard: ManuallyDrop::new(self.lock_timeout(timeout)?),
+            condvar: &self.get_ref().condvar,
+            policy,
+            mutated: false,
+        };
+        Some(f(guard.as_mut()))
+    }
+
+    #[track_caller]
+    pub fn wait<'a>(&self, guard: PinnedMutexGuard<'a, T>) -> PinnedMutexGuard<'a, T> {
+        self.condvar.wait(guard)
+    }
+
+    #[track_caller]
+    pub fn wait_while<'a, F>(
         &self,
         guard: PinnedMutexGuard<'a, T>,
-        mut condition: F,
+        condition: F,
     ) -> PinnedMutexGuard<'a, T>
     where
         F: FnMut(Pin<&mut T>) -> bool,
     {
-        PinnedMutexGuard {
-            guard: self
-                .0
-                .wait_while(guard.guard, move |v| {
-                    // SAFETY: v is never moved.
-                    condition(unsafe { Pin::new_unchecked(v) })
-                })
-                .expect("PinnedMutex does not expose poison"),
-        }
+        self.condvar.wait_while(guard, condition)
+    }
+
+    /// Like [wait_while](Self::wait_while), but gives up after
+    /// `timeout`. Returns the guard and whether it timed out with
+    /// `condition` still true.
+    #[track_caller]
+    pub fn wait_while_for<'a, F>(
+        &self,
+        guard: PinnedMutexGuard<'a, T>,
+        timeout: Duration,
+        condition: F,
+    ) -> (PinnedMutexGuard<'a, T>, bool)
+    where
+        F: FnMut(Pin<&mut T>) -> bool,
+    {
+        self.condvar.wait_while_deadline(guard, timeout, condition)
     }
 
     pub fn notify_one(&self) {
-        self.0.notify_one()
+        self.condvar.notify_one();
     }
 
     pub fn notify_all(&self) {
-        self.0.notify_all()
+        self.condvar.notify_all();
+    }
+}
+
+/// A [PinnedMutexGuard] returned by [PinnedMonitor::lock_notify].
+/// Dropping it releases the lock and then, if `as_mut` or [DerefMut]
+/// was used, notifies the monitor's condvar.
+pub struct NotifyingGuard<'a, T: 'a> {
+    guard: ManuallyDrop<PinnedMutexGuard<'a, T>>,
+    condvar: &'a PinnedCondvar,
+    policy: NotifyPolicy,
+    mutated: bool,
+}
+
+impl<'a, T> NotifyingGuard<'a, T> {
+    /// Provides pinned access to the underlying T.
+    pub fn as_ref(&self) -> Pin<&T> {
+        self.guard.as_ref()
+    }
+
+    /// Provides pinned mutable access to the underlying T, and
+    /// arranges for a notification on drop.
+    pub fn as_mut(&mut self) -> Pin<&mut T> {
+        self.mutated = true;
+        self.guard.as_mut()
+    }
+
+    /// Returns whether the guard will notify when dropped.
+    pub fn is_mutated(&self) -> bool {
+        self.mutated
+    }
+}
+
+impl<'a, T> Deref for NotifyingGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl<'a, T: Unpin> DerefMut for NotifyingGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.mutated = true;
+        &mut self.guard
+    }
+}
+
+impl<'a, T> Drop for NotifyingGuard<'a, T> {
+    fn drop(&mut self) {
+        // SAFETY: the guard is never used again. Unlocking first lets
+        // woken waiters take the lock immediately.
+        unsafe { ManuallyDrop::drop(&mut self.guard) };
+        if self.mutated {
+            match self.policy {
+                NotifyPolicy::One => self.condvar.notify_one(),
+                NotifyPolicy::All => self.condvar.notify_all(),
+            }
+        }
     }
 }
 
@@ -195,4 +2148,586 @@ mod tests {
         let pm: PinnedMutex<DebugTest> = Default::default();
         _ = format!("{:?}", pm);
     }
+
+    #[test]
+    fn mapped_guard() {
+        let pm = pin!(PinnedMutex::new((MustPin::new(), None::<MustPin>)));
+        let pm = pm.as_ref();
+        let mut first = pm.lock().map(|pair| {
+            // SAFETY: the tuple's fields are structurally pinned.
+            unsafe { pair.map_unchecked_mut(|(first, _)| first) }
+        });
+        assert_eq!(0, first.as_mut().inc());
+        assert!(pm.try_lock().is_none());
+        drop(first);
+        let guard = pm.lock().try_map(|pair| {
+            // SAFETY: as above.
+            unsafe { pair.map_unchecked_mut(|(_, second)| second) }.as_pin_mut()
+        });
+        let guard = guard.err().unwrap();
+        assert_eq!(1, guard.0.value);
+    }
+
+    #[test]
+    fn pinned_static() {
+        static COUNTER: PinnedMutex<u32> = PinnedMutex::new(0);
+        *COUNTER.pin_static().lock() += 1;
+        assert_eq!(1, *COUNTER.pin_static().lock());
+    }
+
+    #[test]
+    fn unsized_contents() {
+        let slice: Pin<Box<PinnedMutex<[u32]>>> = Box::pin(PinnedMutex::new([1, 2, 3]));
+        slice.as_ref().lock()[1] = 5;
+        assert_eq!([1, 5, 3], *slice.as_ref().lock());
+        let pinned: Pin<Box<PinnedMutex<dyn Fn() -> u32>>> = Box::pin(PinnedMutex::new(|| 7));
+        assert_eq!(7, (pinned.as_ref().lock())());
+    }
+
+    #[test]
+    fn owned_guard_outlives_borrow() {
+        let pm = Arc::pin(PinnedMutex::new(MustPin::new()));
+        let mut guard = pm.clone().lock_owned();
+        assert!(pm.clone().try_lock_owned().is_none());
+        drop(pm);
+        let mut bump = move || guard.as_mut().inc();
+        assert_eq!(0, bump());
+        assert_eq!(1, bump());
+    }
+
+    #[test]
+    fn with_lock_closures() {
+        let pm = pin!(PinnedMutex::new(MustPin::new()));
+        let pm = pm.as_ref();
+        assert_eq!(0, pm.with_lock(MustPin::inc));
+        let guard = pm.lock();
+        assert_eq!(None, pm.try_with_lock(MustPin::inc));
+        drop(guard);
+        assert_eq!(Some(1), pm.try_with_lock(MustPin::inc));
+        pm.update(|v| {
+            v.inc();
+        });
+        assert_eq!(3, pm.update_and(MustPin::inc));
+    }
+
+    #[test]
+    fn set_replace_take() {
+        let pm = pin!(PinnedMutex::new(Some(1)));
+        let pm = pm.as_ref();
+        pm.set(Some(2));
+        assert_eq!(Some(2), pm.replace(Some(3)));
+        assert_eq!(Some(3), pm.take());
+        assert_eq!(None, *pm.lock());
+    }
+
+    #[test]
+    fn rwlock_readers_share() {
+        let rw = pin!(PinnedRwLock::new(MustPin::new()));
+        let rw = rw.as_ref();
+        rw.write().as_mut().inc();
+        let a = rw.read();
+        let b = rw.read();
+        assert_eq!(1, a.as_ref().get());
+        assert_eq!(1, b.as_ref().get());
+    }
+
+    #[test]
+    fn rwlock_try_variants() {
+        let rw = pin!(PinnedRwLock::new(0));
+        let rw = rw.as_ref();
+        let reader = rw.try_read().unwrap();
+        assert!(rw.try_read().is_some());
+        assert!(rw.try_write().is_none());
+        drop(reader);
+        let writer = rw.try_write().unwrap();
+        assert!(rw.try_read().is_none());
+        drop(writer);
+    }
+
+    #[test]
+    fn rwlock_recovers_poison() {
+        let rw = pin!(PinnedRwLock::new(MustPin::new()));
+        let rw = rw.as_ref();
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                let _guard = rw.write();
+                panic!("poison");
+            })
+            .join()
+            .unwrap_err();
+        });
+        assert!(rw.is_poisoned());
+        assert!(rw.read_result().is_err());
+        let mut guard = match rw.write_result() {
+            Ok(_) => panic!("expected poison"),
+            Err(poison) => poison.into_inner(),
+        };
+        guard.as_mut().inc();
+        drop(guard);
+        rw.clear_poison();
+        assert_eq!(1, rw.read().as_ref().get());
+    }
+
+    #[test]
+    fn rwlock_mapped_guards() {
+        let rw = pin!(PinnedRwLock::new((MustPin::new(), 5)));
+        let rw = rw.as_ref();
+        let mut first = rw.write().map(|pair| {
+            // SAFETY: the tuple's fields are structurally pinned.
+            unsafe { pair.map_unchecked_mut(|(first, _)| first) }
+        });
+        first.as_mut().inc();
+        drop(first);
+        let second = rw.read().map(|pair| {
+            // SAFETY: as above.
+            unsafe { pair.map_unchecked(|(_, second)| second) }
+        });
+        assert_eq!(5, *second);
+        assert!(rw.try_write().is_none());
+        drop(second);
+        let first = rw.read().try_map(|pair| {
+            // SAFETY: as above.
+            Some(unsafe { pair.map_unchecked(|(first, _)| first) })
+        });
+        assert_eq!(1, first.ok().unwrap().as_ref().get());
+    }
+
+    #[cfg(feature = "nightly")]
+    #[test]
+    fn reentrant_lock_reenters() {
+        let lock = pin!(PinnedReentrantLock::new(std::cell::Cell::new(0)));
+        let lock = lock.as_ref();
+        let outer = lock.lock();
+        outer.set(1);
+        let inner = lock.lock();
+        inner.set(inner.get() + 1);
+        drop(inner);
+        assert_eq!(2, outer.as_ref().get());
+        drop(outer);
+        std::thread::scope(|s| {
+            s.spawn(|| assert_eq!(2, lock.lock().get()));
+        });
+    }
+
+    #[test]
+    fn usable_across_catch_unwind() {
+        let pm = pin!(PinnedMutex::new(MustPin::new()));
+        let pm = pm.as_ref();
+        let result = std::panic::catch_unwind(|| {
+            pm.lock().as_mut().inc();
+        });
+        assert!(result.is_ok());
+        let rw = pin!(PinnedRwLock::new(MustPin::new()));
+        let rw = rw.as_ref();
+        let result = std::panic::catch_unwind(|| rw.read().as_ref().get());
+        assert_eq!(0, result.unwrap());
+        assert_eq!(1, pm.lock().as_ref().get());
+    }
+
+    #[test]
+    fn std_mutex_round_trip() {
+        let pm = PinnedMutex::from_std(Mutex::new(vec![1]));
+        Pin::new(&pm).lock().push(2);
+        let mutex = pm.into_std();
+        assert_eq!(vec![1, 2], *mutex.lock().unwrap());
+    }
+
+    #[test]
+    fn from_value() {
+        let pm = pin!(PinnedMutex::from(MustPin::new()));
+        assert_eq!(0, pm.as_ref().lock().as_ref().get());
+    }
+
+    #[test]
+    fn guard_relocks_its_mutex() {
+        let pm = pin!(PinnedMutex::new(MustPin::new()));
+        let mut guard = pm.as_ref().lock();
+        guard.as_mut().inc();
+        let mutex = guard.mutex();
+        guard.unlock();
+        assert_eq!(1, mutex.lock().as_mut().inc());
+    }
+
+    #[test]
+    fn clone_copies_contents() {
+        let pm = pin!(PinnedMutex::new(vec![1, 2]));
+        let copy = pin!(pm.clone());
+        pm.as_ref().lock().push(3);
+        assert_eq!(vec![1, 2], *copy.as_ref().lock());
+        assert_eq!(vec![1, 2, 3], pm.as_ref().get_cloned());
+    }
+
+    #[test]
+    fn eq_by_contents() {
+        let a = PinnedMutex::new(1);
+        let b = PinnedMutex::new(1);
+        assert_eq!(a, b);
+        assert_eq!(a, a);
+        *Pin::new(&b).lock() = 2;
+        assert_ne!(b, a);
+    }
+
+    #[test]
+    fn guard_formats_contents() {
+        let pm = pin!(PinnedMutex::new(7));
+        let guard = pm.as_ref().lock();
+        assert_eq!("7", format!("{:?}", guard));
+        assert_eq!("7", format!("{}", guard));
+    }
+
+    #[test]
+    fn debug_does_not_block() {
+        let pm = pin!(PinnedMutex::new(7));
+        assert_eq!(
+            "PinnedMutex { data: 7, poisoned: false, .. }",
+            format!("{:?}", pm)
+        );
+        let _locked = pm.as_ref().lock();
+        assert_eq!(
+            "PinnedMutex { data: <locked>, poisoned: false, .. }",
+            format!("{:?}", pm)
+        );
+    }
+
+    #[test]
+    fn hierarchy_descending() {
+        let high = pin!(HierarchicalPinnedMutex::<_, 20>::new(1));
+        let low = pin!(HierarchicalPinnedMutex::<_, 10>::new(2));
+        let h = high.as_ref().lock();
+        let l = low.as_ref().lock();
+        assert_eq!(3, *h + *l);
+        drop(h);
+        drop(l);
+        // Out-of-order release leaves no stale levels behind.
+        let _l = low.as_ref().lock();
+    }
+
+    #[test]
+    #[should_panic(expected = "lock order violation")]
+    fn hierarchy_ascending_panics() {
+        let high = pin!(HierarchicalPinnedMutex::<_, 20>::new(()));
+        let low = pin!(HierarchicalPinnedMutex::<_, 10>::new(()));
+        let _l = low.as_ref().lock();
+        let _h = high.as_ref().lock();
+    }
+
+    #[cfg(feature = "fallible")]
+    #[test]
+    fn hierarchy_checked_reports_violation() {
+        let high = pin!(HierarchicalPinnedMutex::<_, 20>::new(()));
+        let low = pin!(HierarchicalPinnedMutex::<_, 10>::new(()));
+        let l = low.as_ref().lock_checked().unwrap();
+        assert_eq!(
+            Some(LockError::WouldDeadlock),
+            high.as_ref().lock_checked().err()
+        );
+        drop(l);
+        let _h = high.as_ref().lock_checked().unwrap();
+        let _l = low.as_ref().lock_checked().unwrap();
+    }
+
+    #[pin_project]
+    struct TwoParts {
+        #[pin]
+        left: MustPin,
+        right: u32,
+    }
+
+    #[test]
+    fn view_projects_field() {
+        let pm = pin!(PinnedMutex::new(TwoParts {
+            left: MustPin::new(),
+            right: 10,
+        }));
+        let left = pm.as_ref().view(|parts| parts.project().left);
+        let right = pm.as_ref().view(|parts| Pin::new(parts.project().right));
+        assert_eq!(0, left.lock().as_mut().inc());
+        *right.lock() += 1;
+        let locked = pm.as_ref().lock();
+        assert_eq!(1, locked.left.value);
+        assert_eq!(11, locked.right);
+    }
+
+    #[test]
+    fn priority_wake_order() {
+        let cv = PinnedPriorityCondvar::new();
+        let pm = pin!(PinnedMutex::new(Vec::new()));
+        let pm = pm.as_ref();
+        thread::scope(|s| {
+            for priority in [1, 3, 2, 3] {
+                let cv = &cv;
+                s.spawn(move || {
+                    let guard = pm.lock();
+                    let mut guard = cv.wait(guard, priority);
+                    guard.push(priority);
+                });
+            }
+            while cv.queue.lock().len() < 4 {
+                thread::yield_now();
+            }
+            for woken in 1..=4 {
+                cv.notify_one();
+                while pm.lock().len() < woken {
+                    thread::yield_now();
+                }
+            }
+        });
+        assert_eq!(vec![3, 3, 2, 1], *pm.lock());
+    }
+
+    #[test]
+    fn get_pin_mut_without_locking() {
+        let mut pm = pin!(PinnedMutex::new(MustPin::new()));
+        assert_eq!(0, pm.as_mut().get_pin_mut().inc());
+        assert_eq!(1, pm.as_ref().lock().as_ref().get());
+    }
+
+    #[test]
+    fn into_inner_after_poison() {
+        let pm = PinnedMutex::new(vec![1]);
+        assert_eq!(vec![1], PinnedMutex::new(vec![1]).into_inner());
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                let _guard = Pin::new(&pm).lock();
+                panic!("poison");
+            })
+            .join()
+            .unwrap_err();
+        });
+        let poison = pm.into_inner_result().unwrap_err();
+        assert_eq!(vec![1], poison.into_inner());
+    }
+
+    #[test]
+    fn lock_timeout_gives_up() {
+        let pm = pin!(PinnedMutex::new(0));
+        let pm = pm.as_ref();
+        let guard = pm.lock();
+        std::thread::scope(|s| {
+            s.spawn(|| assert!(pm.lock_timeout(Duration::from_millis(5)).is_none()));
+        });
+        drop(guard);
+        assert!(pm.lock_timeout(Duration::from_millis(5)).is_some());
+    }
+
+    #[test]
+    fn try_lock_deadline_gives_up() {
+        let pm = pin!(PinnedMutex::new(0));
+        let pm = pm.as_ref();
+        let guard = pm.lock();
+        std::thread::scope(|s| {
+            s.spawn(|| assert!(pm.try_lock_deadline(Instant::now()).is_none()));
+        });
+        drop(guard);
+        assert!(pm.try_lock_deadline(Deadline::Never).is_some());
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "already locked by the current thread")]
+    fn relock_panics_in_debug() {
+        let pm = pin!(PinnedMutex::new(0));
+        let _guard = pm.as_ref().lock();
+        let _again = pm.as_ref().lock();
+    }
+
+    #[test]
+    fn try_lock_while_held() {
+        let pm = pin!(PinnedMutex::new(MustPin::new()));
+        let mut locked = pm.as_ref().try_lock().unwrap();
+        assert_eq!(0, locked.as_mut().inc());
+        assert!(pm.as_ref().try_lock().is_none());
+        drop(locked);
+        assert_eq!(1, pm.as_ref().try_lock().unwrap().as_ref().get());
+    }
+
+    #[test]
+    fn lock_result_recovers_poison() {
+        let pm = pin!(PinnedMutex::new(MustPin::new()));
+        let pm = pm.as_ref();
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                pm.lock().as_mut().inc();
+                let _guard = pm.lock();
+                panic!("poison");
+            })
+            .join()
+            .unwrap_err();
+        });
+        assert!(pm.is_poisoned());
+        let mut guard = match pm.lock_result() {
+            Ok(_) => panic!("expected poison"),
+            Err(poison) => poison.into_inner(),
+        };
+        assert_eq!(1, guard.as_mut().inc());
+        drop(guard);
+        pm.clear_poison();
+        assert!(!pm.is_poisoned());
+        assert_eq!(2, pm.lock().as_ref().get());
+    }
+
+    #[test]
+    fn shared_across_threads() {
+        let shared = PinnedShared::new(MustPin::new());
+        let weak = shared.downgrade();
+        let workers: Vec<_> = (0..4)
+            .map(|_| {
+                shared.spawn(|shared| {
+                    shared.with_lock(|v| {
+                        v.inc();
+                    })
+                })
+            })
+            .collect();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+        assert_eq!(4, shared.lock().as_ref().get());
+        assert!(weak.upgrade().unwrap().ptr_eq(&shared));
+        drop(shared);
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[test]
+    fn fifo_wake_order() {
+        let cv = PinnedFifoCondvar::new();
+        let pm = pin!(PinnedMutex::new(Vec::new()));
+        let pm = pm.as_ref();
+        thread::scope(|s| {
+            for id in 0..4 {
+                let cv = &cv;
+                s.spawn(move || {
+                    let guard = pm.lock();
+                    let mut guard = cv.wait(guard);
+                    guard.push(id);
+                });
+                while cv.queue.lock().len() <= id {
+                    thread::yield_now();
+                }
+            }
+            for woken in 1..=4 {
+                cv.notify_one();
+                while pm.lock().len() < woken {
+                    thread::yield_now();
+                }
+            }
+        });
+        assert_eq!(vec![0, 1, 2, 3], *pm.lock());
+    }
+
+    #[test]
+    fn swap_contents() {
+        let a = pin!(PinnedMutex::new(vec![1]));
+        let b = pin!(PinnedMutex::new(vec![2, 3]));
+        a.as_ref().swap(b.as_ref());
+        assert_eq!(vec![2, 3], *a.as_ref().lock());
+        a.as_ref().swap(a.as_ref());
+        let held = b.as_ref().lock();
+        assert!(!a.as_ref().try_swap(b.as_ref()));
+        drop(held);
+        assert!(a.as_ref().try_swap(b.as_ref()));
+        assert_eq!(vec![1], *a.as_ref().lock());
+    }
+
+    #[test]
+    fn scope_workers() {
+        let pm = pin!(PinnedMutex::new(MustPin::new()));
+        pm.as_ref().scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|scope| scope.with_lock(|n| n.inc()));
+            }
+        });
+        assert_eq!(4, pm.as_ref().lock().as_ref().get());
+    }
+
+    #[test]
+    fn wait_deadline_times_out() {
+        let cv = PinnedCondvar::new();
+        let pm = pin!(PinnedMutex::new(0));
+        let timeout = std::time::Duration::from_millis(10);
+        let (guard, timed_out) = cv.wait_deadline(pm.as_ref().lock(), timeout);
+        assert!(timed_out);
+        let (guard, timed_out) = cv.wait_while_deadline(guard, timeout, |n| *n == 0);
+        assert!(timed_out);
+        let (_guard, timed_out) = cv.wait_while_deadline(guard, Deadline::Never, |n| *n != 0);
+        assert!(!timed_out);
+    }
+
+    #[test]
+    fn monitor_notifies_on_mutation() {
+        let monitor = pin!(PinnedMonitor::new(0));
+        let monitor = monitor.as_ref();
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                let guard = monitor.wait_while(monitor.lock(), |n| *n == 0);
+                assert_eq!(1, *guard);
+            });
+            let reader = monitor.lock_notify(NotifyPolicy::One);
+            assert!(!reader.is_mutated());
+            drop(reader);
+            *monitor.lock_notify(NotifyPolicy::All) += 1;
+        });
+    }
+
+    #[test]
+    fn monitor_timeouts() {
+        let monitor = pin!(PinnedMonitor::new(0));
+        let monitor = monitor.as_ref();
+        let timeout = std::time::Duration::from_millis(10);
+        let (guard, timed_out) = monitor.wait_while_for(monitor.lock(), timeout, |n| *n == 0);
+        assert!(timed_out);
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                assert!(monitor.lock_timeout(timeout).is_none());
+                assert_eq!(
+                    None,
+                    monitor.update_and_notify_within(timeout, NotifyPolicy::One, |_| ())
+                );
+            })
+            .join()
+            .unwrap();
+        });
+        drop(guard);
+        assert_eq!(
+            Some(1),
+            monitor.update_and_notify_within(timeout, NotifyPolicy::All, |mut n| {
+                *n += 1;
+                *n
+            })
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "fallible")]
+    fn lock_checked_reports_poison() {
+        let pm = pin!(PinnedMutex::new(0));
+        let pm = pm.as_ref();
+        assert!(pm.lock_checked().is_ok());
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                let _guard = pm.lock();
+                panic!("poison");
+            })
+            .join()
+            .unwrap_err();
+        });
+        assert_eq!(Some(LockError::Poisoned), pm.lock_checked().err());
+        #[cfg(debug_assertions)]
+        {
+            let _guard = pm.lock_result();
+            assert_eq!(Some(LockError::WouldDeadlock), pm.lock_checked().err());
+        }
+    }
+
+    #[test]
+    fn epoch_catches_earlier_notify() {
+        let cv = PinnedCondvar::new();
+        let pm = pin!(PinnedMutex::new(0));
+        let epoch = cv.epoch();
+        cv.notify_one();
+        // Does not block.
+        let guard = cv.wait_for_epoch(pm.as_ref().lock(), epoch);
+        assert_eq!(epoch.wrapping_add(1), cv.epoch());
+        drop(guard);
+    }
 }
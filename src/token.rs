@@ -0,0 +1,156 @@
+use std::cell::UnsafeCell;
+use std::fmt;
+use std::marker::PhantomData;
+use std::pin::Pin;
+
+/// Invariant in `'brand`, so tokens and cells from different
+/// [PinnedToken::scope] calls can never be mixed up.
+type Brand<'brand> = PhantomData<fn(&'brand ()) -> &'brand ()>;
+
+/// Grants access to every [PinnedTokenCell] with the same brand.
+/// Holding `&PinnedToken` permits shared pinned access to all of
+/// them; holding `&mut PinnedToken` permits exclusive pinned access
+/// to one at a time.
+///
+/// Tokens are only created by [PinnedToken::scope], which gives each
+/// call a fresh, unnameable brand.
+pub struct PinnedToken<'brand> {
+    _brand: Brand<'brand>,
+}
+
+impl<'brand> PinnedToken<'brand> {
+    /// Runs `f` with a new token whose brand is unique to this call.
+    pub fn scope<R>(f: impl for<'new> FnOnce(PinnedToken<'new>) -> R) -> R {
+        f(PinnedToken {
+            _brand: PhantomData,
+        })
+    }
+}
+
+impl<'brand> fmt::Debug for PinnedToken<'brand> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PinnedToken").finish()
+    }
+}
+
+/// Provides [structural
+/// pinning](https://doc.rust-lang.org/std/pin/index.html#projections-and-structural-pinning)
+/// without a lock. Access is granted by borrowing the cell's
+/// [PinnedToken], so the borrow checker, rather than a runtime lock,
+/// enforces exclusion.
+pub struct PinnedTokenCell<'brand, T> {
+    _brand: Brand<'brand>,
+    value: UnsafeCell<T>,
+}
+
+// SAFETY: like GhostCell, sharing a cell across threads allows both
+// &T (through a shared token) and &mut T (through a token moved to
+// another thread), so T must be Send + Sync.
+unsafe impl<'brand, T: Send> Send for PinnedTokenCell<'brand, T> {}
+unsafe impl<'brand, T: Send + Sync> Sync for PinnedTokenCell<'brand, T> {}
+
+impl<'brand, T> PinnedTokenCell<'brand, T> {
+    pub fn new(value: T) -> Self {
+        Self {
+            _brand: PhantomData,
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// Provides pinned access to the underlying T.
+    pub fn borrow<'a>(self: Pin<&'a Self>, _token: &'a PinnedToken<'brand>) -> Pin<&'a T> {
+        // SAFETY: the shared token borrow excludes borrow_mut for 'a,
+        // and the cell is pinned.
+        unsafe { Pin::new_unchecked(&*self.value.get()) }
+    }
+
+    /// Provides pinned mutable access to the underlying T.
+    pub fn borrow_mut<'a>(
+        self: Pin<&'a Self>,
+        _token: &'a mut PinnedToken<'brand>,
+    ) -> Pin<&'a mut T> {
+        // SAFETY: the exclusive token borrow excludes every other
+        // access to cells of this brand for 'a, and the cell is
+        // pinned.
+        unsafe { Pin::new_unchecked(&mut *self.value.get()) }
+    }
+
+    /// Provides pinned mutable access without the token, since
+    /// `&mut self` already proves exclusivity.
+    pub fn get_pin_mut(self: Pin<&mut Self>) -> Pin<&mut T> {
+        // SAFETY: the cell is pinned, so its contents are too.
+        unsafe { self.map_unchecked_mut(|cell| cell.value.get_mut()) }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+impl<'brand, T: Default> Default for PinnedTokenCell<'brand, T> {
+    fn default() -> Self {
+        Self::new(Default::default())
+    }
+}
+
+impl<'brand, T> fmt::Debug for PinnedTokenCell<'brand, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PinnedTokenCell").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pin_project::pin_project;
+    use std::{marker::PhantomPinned, pin::pin};
+
+    #[pin_project(UnsafeUnpin)]
+    struct MustPin {
+        value: u32,
+        pinned: PhantomPinned,
+    }
+
+    impl MustPin {
+        fn new() -> Self {
+            Self {
+                value: 0,
+                pinned: PhantomPinned,
+            }
+        }
+
+        fn inc(self: Pin<&mut Self>) -> u32 {
+            let value = self.project().value;
+            let prev = *value;
+            *value += 1;
+            prev
+        }
+
+        fn get(self: Pin<&Self>) -> u32 {
+            *self.project_ref().value
+        }
+    }
+
+    #[test]
+    fn token_grants_access() {
+        PinnedToken::scope(|mut token| {
+            let a = pin!(PinnedTokenCell::new(MustPin::new()));
+            let b = pin!(PinnedTokenCell::new(MustPin::new()));
+            let (a, b) = (a.as_ref(), b.as_ref());
+            assert_eq!(0, a.borrow_mut(&mut token).inc());
+            assert_eq!(1, a.borrow_mut(&mut token).inc());
+            assert_eq!(0, b.borrow_mut(&mut token).inc());
+            assert_eq!(2, a.borrow(&token).get());
+            assert_eq!(1, b.borrow(&token).get());
+        });
+    }
+
+    #[test]
+    fn get_mut_without_token() {
+        PinnedToken::scope(|token| {
+            let mut cell = pin!(PinnedTokenCell::new(MustPin::new()));
+            cell.as_mut().get_pin_mut().inc();
+            assert_eq!(1, cell.as_ref().borrow(&token).get());
+        });
+    }
+}
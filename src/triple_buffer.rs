@@ -0,0 +1,187 @@
+use std::cell::UnsafeCell;
+use std::fmt;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// Set in the shared index when the middle buffer holds a snapshot
+/// the reader has not seen.
+const DIRTY: u8 = 0b100;
+const INDEX: u8 = 0b011;
+
+/// A triple buffer: one writer fills a pinned back buffer and
+/// publishes it, and one reader takes the latest published snapshot.
+/// Neither side ever blocks the other.
+///
+/// Of the three buffers, the writer owns one, the reader owns one,
+/// and the third is handed between them by an atomic swap. Buffers
+/// never move, so `T` may be `!Unpin`. Buffers are recycled, so after
+/// a publish the back buffer holds an older snapshot, not a copy of
+/// the one just published.
+pub struct PinnedTripleBuffer<T> {
+    buffers: [UnsafeCell<T>; 3],
+    shared: AtomicU8,
+    back: u8,
+    front: u8,
+}
+
+// SAFETY: each buffer is accessed by at most one handle at a time,
+// and handles only exist while split borrows the whole buffer.
+unsafe impl<T: Send> Sync for PinnedTripleBuffer<T> {}
+
+impl<T> PinnedTripleBuffer<T> {
+    /// Creates a triple buffer whose three buffers are initialized by
+    /// calling `init`.
+    pub fn from_fn(mut init: impl FnMut() -> T) -> Self {
+        Self {
+            buffers: [
+                UnsafeCell::new(init()),
+                UnsafeCell::new(init()),
+                UnsafeCell::new(init()),
+            ],
+            shared: AtomicU8::new(1),
+            back: 0,
+            front: 2,
+        }
+    }
+
+    /// Splits the buffer into its writer and reader halves, which may
+    /// be sent to different threads.
+    pub fn split(
+        self: Pin<&mut Self>,
+    ) -> (
+        PinnedTripleBufferWriter<'_, T>,
+        PinnedTripleBufferReader<'_, T>,
+    ) {
+        // SAFETY: the fields are only borrowed, never moved.
+        let this = unsafe { self.get_unchecked_mut() };
+        (
+            PinnedTripleBufferWriter {
+                buffers: &this.buffers,
+                shared: &this.shared,
+                back: &mut this.back,
+            },
+            PinnedTripleBufferReader {
+                buffers: &this.buffers,
+                shared: &this.shared,
+                front: &mut this.front,
+            },
+        )
+    }
+}
+
+impl<T: Default> Default for PinnedTripleBuffer<T> {
+    fn default() -> Self {
+        Self::from_fn(Default::default)
+    }
+}
+
+impl<T> fmt::Debug for PinnedTripleBuffer<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PinnedTripleBuffer").finish_non_exhaustive()
+    }
+}
+
+/// The writing half of a [PinnedTripleBuffer].
+pub struct PinnedTripleBufferWriter<'a, T> {
+    buffers: &'a [UnsafeCell<T>; 3],
+    shared: &'a AtomicU8,
+    back: &'a mut u8,
+}
+
+// SAFETY: the writer only touches the back buffer, which it owns.
+unsafe impl<'a, T: Send> Send for PinnedTripleBufferWriter<'a, T> {}
+
+impl<'a, T> PinnedTripleBufferWriter<'a, T> {
+    /// Provides pinned mutable access to the back buffer.
+    pub fn back(&mut self) -> Pin<&mut T> {
+        // SAFETY: the back buffer is owned by this writer, and buffers
+        // are pinned by split.
+        unsafe { Pin::new_unchecked(&mut *self.buffers[*self.back as usize].get()) }
+    }
+
+    /// Publishes the back buffer as the latest snapshot and takes the
+    /// previous middle buffer as the new back buffer.
+    pub fn publish(&mut self) {
+        let old = self.shared.swap(*self.back | DIRTY, Ordering::AcqRel);
+        *self.back = old & INDEX;
+    }
+}
+
+/// The reading half of a [PinnedTripleBuffer].
+pub struct PinnedTripleBufferReader<'a, T> {
+    buffers: &'a [UnsafeCell<T>; 3],
+    shared: &'a AtomicU8,
+    front: &'a mut u8,
+}
+
+// SAFETY: the reader only touches the front buffer, which it owns.
+unsafe impl<'a, T: Send> Send for PinnedTripleBufferReader<'a, T> {}
+
+impl<'a, T> PinnedTripleBufferReader<'a, T> {
+    /// Returns true if a snapshot has been published since the last
+    /// [read](Self::read).
+    pub fn has_update(&self) -> bool {
+        self.shared.load(Ordering::Relaxed) & DIRTY != 0
+    }
+
+    /// Returns the latest published snapshot, or the previous one
+    /// again if nothing new has been published.
+    pub fn read(&mut self) -> Pin<&T> {
+        if self.has_update() {
+            let old = self.shared.swap(*self.front, Ordering::AcqRel);
+            *self.front = old & INDEX;
+        }
+        // SAFETY: the front buffer is owned by this reader, and
+        // buffers are pinned by split.
+        unsafe { Pin::new_unchecked(&*self.buffers[*self.front as usize].get()) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pin_project::pin_project;
+    use std::{marker::PhantomPinned, pin::pin};
+
+    #[pin_project(UnsafeUnpin)]
+    #[derive(Default)]
+    struct MustPin {
+        value: u32,
+        pinned: PhantomPinned,
+    }
+
+    #[test]
+    fn latest_snapshot_wins() {
+        let tb = pin!(PinnedTripleBuffer::<MustPin>::default());
+        let (mut writer, mut reader) = tb.split();
+        assert!(!reader.has_update());
+        for value in 1..=3 {
+            *writer.back().project().value = value;
+            writer.publish();
+        }
+        assert!(reader.has_update());
+        assert_eq!(3, reader.read().value);
+        assert!(!reader.has_update());
+        assert_eq!(3, reader.read().value);
+    }
+
+    #[test]
+    fn across_threads() {
+        let tb = pin!(PinnedTripleBuffer::from_fn(|| 0u64));
+        let (mut writer, mut reader) = tb.split();
+        std::thread::scope(|s| {
+            s.spawn(move || {
+                for value in 1..=1000 {
+                    *writer.back() = value;
+                    writer.publish();
+                }
+            });
+            let mut last = 0;
+            while last < 1000 {
+                let value = *reader.read();
+                assert!(value >= last);
+                last = value;
+            }
+        });
+    }
+}
@@ -0,0 +1,423 @@
+use std::cell::UnsafeCell;
+use std::fmt;
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+use windows_sys::Win32::Foundation::{GetLastError, ERROR_TIMEOUT};
+use windows_sys::Win32::System::Threading::{
+    AcquireSRWLockExclusive, ReleaseSRWLockExclusive, SleepConditionVariableSRW,
+    TryAcquireSRWLockExclusive, WakeAllConditionVariable, WakeConditionVariable,
+    CONDITION_VARIABLE, CONDITION_VARIABLE_INIT, INFINITE, SRWLOCK, SRWLOCK_INIT,
+};
+
+use crate::Deadline;
+#[cfg(feature = "fallible")]
+use crate::LockError;
+
+/// Provides [structural
+/// pinning](https://doc.rust-lang.org/std/pin/index.html#projections-and-structural-pinning)
+/// atop a native Windows
+/// [SRWLOCK](https://learn.microsoft.com/en-us/windows/win32/sync/slim-reader-writer--srw--locks).
+pub struct PinnedMutex<T> {
+    lock: UnsafeCell<SRWLOCK>,
+    waiters: AtomicUsize,
+    data: UnsafeCell<T>,
+}
+
+// SAFETY: SRWLOCK provides mutual exclusion, so sharing the mutex
+// only requires T to be sendable between threads, as with
+// std::sync::Mutex.
+unsafe impl<T: Send> Send for PinnedMutex<T> {}
+unsafe impl<T: Send> Sync for PinnedMutex<T> {}
+
+impl<T> PinnedMutex<T> {
+    pub fn new(init: T) -> Self {
+        Self {
+            lock: UnsafeCell::new(SRWLOCK_INIT),
+            waiters: AtomicUsize::new(0),
+            data: UnsafeCell::new(init),
+        }
+    }
+
+    /// Acquires the lock and returns a guard.
+    ///
+    /// SRWLOCK does not support poisoning. Neither does this.
+    pub fn lock(self: Pin<&Self>) -> PinnedMutexGuard<'_, T> {
+        let mutex = self.get_ref();
+        if let Some(guard) = mutex.try_lock_inner() {
+            return guard;
+        }
+        mutex.waiters.fetch_add(1, Ordering::Relaxed);
+        // SAFETY: the SRWLOCK is initialized and lives as long as self.
+        unsafe { AcquireSRWLockExclusive(mutex.lock.get()) };
+        mutex.waiters.fetch_sub(1, Ordering::Relaxed);
+        PinnedMutexGuard {
+            mutex,
+            _not_send: PhantomData,
+        }
+    }
+
+    /// Always succeeds: an SRWLOCK has no error or poison state, and
+    /// AcquireSRWLockExclusive simply waits. Exists so code written
+    /// against every backend's `lock_checked` builds on Windows.
+    #[cfg_attr(docsrs, doc(cfg(feature = "fallible")))]
+    #[cfg(feature = "fallible")]
+    pub fn lock_checked(self: Pin<&Self>) -> Result<PinnedMutexGuard<'_, T>, LockError> {
+        Ok(self.lock())
+    }
+
+    /// Returns an estimate of how many threads are blocked waiting
+    /// for the lock. It changes concurrently, so treat it as a hint.
+    pub fn waiters(&self) -> usize {
+        self.waiters.load(Ordering::Relaxed)
+    }
+
+    /// Returns true if some thread appears to be blocked waiting for
+    /// the lock. Adaptive code can, for example, do more work per
+    /// acquisition while the lock is contended.
+    pub fn is_contended(&self) -> bool {
+        self.waiters() > 0
+    }
+
+    fn try_lock_inner(&self) -> Option<PinnedMutexGuard<'_, T>> {
+        // SAFETY: the SRWLOCK is initialized and lives as long as self.
+        if unsafe { TryAcquireSRWLockExclusive(self.lock.get()) } != 0 {
+            Some(PinnedMutexGuard {
+                mutex: self,
+                _not_send: PhantomData,
+            })
+        } else {
+            None
+        }
+    }
+}
+
+impl<T: Default> Default for PinnedMutex<T> {
+    fn default() -> Self {
+        Self::new(Default::default())
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for PinnedMutex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("PinnedMutex");
+        match self.try_lock_inner() {
+            Some(guard) => d.field("data", &&*guard),
+            None => d.field("data", &format_args!("<locked>")),
+        };
+        d.finish_non_exhaustive()
+    }
+}
+
+/// Provides access to mutex's contents. [Deref] to `&T` is always
+/// possible. [DerefMut] to `&mut T` is only possive if T is `Unpin`.
+///
+/// `as_ref` and `as_mut` project structural pinning.
+pub struct PinnedMutexGuard<'a, T: 'a> {
+    mutex: &'a PinnedMutex<T>,
+    _not_send: PhantomData<*mut ()>,
+}
+
+// SAFETY: the guard only hands out &T to other threads. It is not
+// Send because SRWLOCK must be released by the acquiring thread.
+unsafe impl<'a, T: Sync> Sync for PinnedMutexGuard<'a, T> {}
+
+impl<'a, T> PinnedMutexGuard<'a, T> {
+    /// Provides pinned access to the underlying T.
+    pub fn as_ref(&self) -> Pin<&T> {
+        // PinnedMutex::lock requires the mutex is pinned.
+        unsafe { Pin::new_unchecked(&*self.mutex.data.get()) }
+    }
+
+    /// Provides pinned mutable access to the underlying T.
+    pub fn as_mut(&mut self) -> Pin<&mut T> {
+        // PinnedMutex::lock requires the mutex is pinned.
+        // &mut self guarantees as_ref() cannot alias.
+        unsafe { Pin::new_unchecked(&mut *self.mutex.data.get()) }
+    }
+}
+
+impl<'a, T> Deref for PinnedMutexGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        // SAFETY: the guard holds the lock.
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<'a, T: Unpin> DerefMut for PinnedMutexGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        // SAFETY: the guard holds the lock, and T is Unpin.
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<'a, T> Drop for PinnedMutexGuard<'a, T> {
+    fn drop(&mut self) {
+        // SAFETY: the guard holds the lock on this thread.
+        unsafe { ReleaseSRWLockExclusive(self.mutex.lock.get()) }
+    }
+}
+
+/// A condition variable built on a native Windows
+/// [CONDITION_VARIABLE](https://learn.microsoft.com/en-us/windows/win32/sync/condition-variables).
+pub struct PinnedCondvar {
+    condvar: UnsafeCell<CONDITION_VARIABLE>,
+    epoch: AtomicUsize,
+}
+
+// SAFETY: CONDITION_VARIABLE is designed to be shared between threads.
+unsafe impl Send for PinnedCondvar {}
+unsafe impl Sync for PinnedCondvar {}
+
+impl Default for PinnedCondvar {
+    fn default() -> Self {
+        Self {
+            condvar: UnsafeCell::new(CONDITION_VARIABLE_INIT),
+            epoch: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl fmt::Debug for PinnedCondvar {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PinnedCondvar").finish_non_exhaustive()
+    }
+}
+
+impl PinnedCondvar {
+    pub fn new() -> PinnedCondvar {
+        Default::default()
+    }
+
+    pub fn wait<'a, T>(&self, guard: PinnedMutexGuard<'a, T>) -> PinnedMutexGuard<'a, T> {
+        // SAFETY: the guard holds the lock, which the kernel releases
+        // and reacquires around the sleep.
+        let ok = unsafe {
+            SleepConditionVariableSRW(self.condvar.get(), guard.mutex.lock.get(), INFINITE, 0)
+        };
+        assert!(
+            ok != 0,
+            "SleepConditionVariableSRW failed without a timeout"
+        );
+        guard
+    }
+
+    pub fn wait_while<'a, T, F>(
+        &self,
+        mut guard: PinnedMutexGuard<'a, T>,
+        mut condition: F,
+    ) -> PinnedMutexGuard<'a, T>
+    where
+        F: FnMut(Pin<&mut T>) -> bool,
+    {
+        while condition(guard.as_mut()) {
+            guard = self.wait(guard);
+        }
+        guard
+    }
+
+    /// Like [wait](Self::wait), but gives up at `deadline`. Returns the
+    /// guard and whether the deadline passed.
+    pub fn wait_deadline<'a, T>(
+        &self,
+        guard: PinnedMutexGuard<'a, T>,
+        deadline: impl Into<Deadline>,
+    ) -> (PinnedMutexGuard<'a, T>, bool) {
+        let at = deadline.into().to_instant();
+        let millis = match at {
+            None => INFINITE,
+            Some(at) => {
+                let timeout = at.saturating_duration_since(Instant::now());
+                // Round up so the wait does not end before the
+                // deadline, and stay below INFINITE.
+                let millis = timeout.as_nanos().div_ceil(1_000_000);
+                millis.min(u128::from(INFINITE - 1)) as u32
+            }
+        };
+        // SAFETY: as in wait.
+        let ok = unsafe {
+            SleepConditionVariableSRW(self.condvar.get(), guard.mutex.lock.get(), millis, 0)
+        };
+        if ok != 0 {
+            return (guard, false);
+        }
+        let error = unsafe { GetLastError() };
+        assert_eq!(error, ERROR_TIMEOUT, "SleepConditionVariableSRW failed");
+        // A clamped wait may time out before the deadline.
+        let timed_out = at.is_some_and(|at| Instant::now() >= at);
+        (guard, timed_out)
+    }
+
+    /// Like [wait_while](Self::wait_while), but gives up at
+    /// `deadline`. Returns the guard and whether the deadline passed
+    /// with `condition` still true.
+    pub fn wait_while_deadline<'a, T, F>(
+        &self,
+        mut guard: PinnedMutexGuard<'a, T>,
+        deadline: impl Into<Deadline>,
+        mut condition: F,
+    ) -> (PinnedMutexGuard<'a, T>, bool)
+    where
+        F: FnMut(Pin<&mut T>) -> bool,
+    {
+        let deadline = Deadline::from(deadline.into().to_instant());
+        while condition(guard.as_mut()) {
+            let (next, timed_out) = self.wait_deadline(guard, deadline);
+            guard = next;
+            if timed_out {
+                let still = condition(guard.as_mut());
+                return (guard, still);
+            }
+        }
+        (guard, false)
+    }
+
+    /// Returns the number of notifications sent so far, wrapping on
+    /// overflow. Record it before releasing the lock, and pass it to
+    /// [wait_for_epoch](Self::wait_for_epoch) to wait for a
+    /// notification sent after that point.
+    pub fn epoch(&self) -> usize {
+        self.epoch.load(Ordering::SeqCst)
+    }
+
+    /// Blocks until a notification has been sent since
+    /// [epoch](Self::epoch) returned `epoch`, or returns immediately if
+    /// one already has.
+    ///
+    /// A notification is never missed if it is sent while holding the
+    /// mutex, the same contract as any condvar predicate.
+    pub fn wait_for_epoch<'a, T>(
+        &self,
+        guard: PinnedMutexGuard<'a, T>,
+        epoch: usize,
+    ) -> PinnedMutexGuard<'a, T> {
+        self.wait_while(guard, |_| self.epoch() == epoch)
+    }
+
+    pub fn notify_one(&self) {
+        self.epoch.fetch_add(1, Ordering::SeqCst);
+        unsafe { WakeConditionVariable(self.condvar.get()) }
+    }
+
+    pub fn notify_all(&self) {
+        self.epoch.fetch_add(1, Ordering::SeqCst);
+        unsafe { WakeAllConditionVariable(self.condvar.get()) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pin_project::pin_project;
+    use std::{marker::PhantomPinned, pin::pin};
+
+    #[test]
+    fn mutate_through_lock() {
+        let pm = pin!(PinnedMutex::new(15));
+        let mut locked = pm.as_ref().lock();
+        *locked = 16;
+    }
+
+    #[pin_project(UnsafeUnpin)]
+    struct MustPin {
+        value: u32,
+        pinned: PhantomPinned,
+    }
+
+    impl MustPin {
+        fn new() -> Self {
+            Self {
+                value: 0,
+                pinned: PhantomPinned,
+            }
+        }
+
+        fn inc(self: Pin<&mut Self>) -> u32 {
+            let value = self.project().value;
+            let prev = *value;
+            *value += 1;
+            prev
+        }
+
+        fn get(self: Pin<&Self>) -> u32 {
+            *self.project_ref().value
+        }
+    }
+
+    #[test]
+    fn pinned_method() {
+        let pm = pin!(PinnedMutex::new(MustPin::new()));
+        let mut locked = pm.as_ref().lock();
+        assert_eq!(0, locked.as_mut().inc());
+        assert_eq!(1, locked.as_mut().inc());
+        assert_eq!(2, locked.as_ref().get());
+    }
+
+    #[test]
+    fn cond_var() {
+        let cv = PinnedCondvar::new();
+        let pm = pin!(PinnedMutex::new(MustPin::new()));
+        let mut locked = pm.as_ref().lock();
+        locked.as_mut().inc();
+        let locked = cv.wait_while(locked, |pinned_contents| {
+            pinned_contents.as_ref().get() == 0
+        });
+        drop(locked);
+        cv.notify_one();
+        cv.notify_all();
+    }
+
+    #[test]
+    fn debug_while_locked() {
+        let pm = pin!(PinnedMutex::new(7));
+        assert!(format!("{:?}", pm).contains('7'));
+        let _locked = pm.as_ref().lock();
+        assert!(format!("{:?}", pm).contains("<locked>"));
+    }
+
+    #[test]
+    fn wait_deadline_times_out() {
+        let cv = PinnedCondvar::new();
+        let pm = pin!(PinnedMutex::new(0));
+        let timeout = std::time::Duration::from_millis(10);
+        let (guard, timed_out) = cv.wait_deadline(pm.as_ref().lock(), timeout);
+        assert!(timed_out);
+        let (guard, timed_out) = cv.wait_while_deadline(guard, timeout, |n| *n == 0);
+        assert!(timed_out);
+        let (_guard, timed_out) = cv.wait_while_deadline(guard, Deadline::Never, |n| *n != 0);
+        assert!(!timed_out);
+    }
+
+    #[test]
+    fn contention_hint() {
+        let pm = pin!(PinnedMutex::new(0));
+        let pm = pm.as_ref();
+        assert!(!pm.is_contended());
+        let guard = pm.lock();
+        std::thread::scope(|s| {
+            s.spawn(|| *pm.lock() += 1);
+            while !pm.is_contended() {
+                std::thread::yield_now();
+            }
+            assert_eq!(1, pm.waiters());
+            drop(guard);
+        });
+        assert!(!pm.is_contended());
+        assert_eq!(1, *pm.lock());
+    }
+
+    #[test]
+    fn epoch_catches_earlier_notify() {
+        let cv = PinnedCondvar::new();
+        let pm = pin!(PinnedMutex::new(0));
+        let epoch = cv.epoch();
+        cv.notify_one();
+        // Does not block.
+        let guard = cv.wait_for_epoch(pm.as_ref().lock(), epoch);
+        assert_eq!(epoch.wrapping_add(1), cv.epoch());
+        drop(guard);
+    }
+}